@@ -1,47 +1,89 @@
+use crate::token::Span;
+
 #[derive(Debug, Clone)]
 pub struct Program { pub items: Vec<Item> }
 
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
-pub enum Item { Class(ClassDecl), Func(FuncDecl), Let(LetDecl) }
+pub enum Item { Class(ClassDecl), Func(FuncDecl), Let(LetDecl), Import(ImportDecl) }
+
+/// `import "út/modul.rn";` — az út az importáló fájlhoz képest értendő; a modul a fájlnevéből
+/// (kiterjesztés és lokálé-utótag nélkül) képzett néven érhető el: `modul.f(...)`.
+#[derive(Debug, Clone)]
+pub struct ImportDecl { pub path: String, pub span: Span }
 
+/// `parent`: az ős neve, más modulból `modul.Osztály` alakban.
 #[derive(Debug, Clone)]
-pub struct ClassDecl { pub name: String, pub methods: Vec<FuncDecl> }
+pub struct ClassDecl { pub name: String, pub parent: Option<(String, Span)>, pub fields: Vec<FieldDecl>, pub methods: Vec<FuncDecl>, pub span: Span }
 
+/// `let név = kezdőérték;` vagy `var név;` az osztálytörzsben; kezdőérték nélkül Void.
+/// `mutable`: `var` mező, különben csak a kezdőértéke állítja be.
 #[derive(Debug, Clone)]
-pub struct FuncDecl { pub name: String, pub params: Vec<String>, pub body: Block }
+pub struct FieldDecl { pub name: String, pub mutable: bool, pub init: Option<Expr>, pub span: Span }
 
 #[derive(Debug, Clone)]
-pub struct LetDecl { pub name: String, pub init: Expr }
+pub struct FuncDecl { pub name: String, pub params: Vec<String>, pub body: Block, pub span: Span }
 
+/// `mutable`: `var` (újra értékadható), különben `let`.
 #[derive(Debug, Clone)]
-pub struct Block { pub stmts: Vec<Stmt> }
+pub struct LetDecl { pub name: String, pub mutable: bool, pub init: Expr, pub span: Span }
 
+/// `span`: a kapcsos zárójelek közti teljes blokk; a függvénytörzs implicit `return`-je ide mutat.
 #[derive(Debug, Clone)]
-pub enum Stmt {
+pub struct Block { pub stmts: Vec<Stmt>, pub span: Span }
+
+#[derive(Debug, Clone)]
+pub struct Stmt { pub kind: StmtKind, pub span: Span }
+
+#[derive(Debug, Clone)]
+pub enum StmtKind {
     Let(LetDecl),
-    Assign { name: String, value: Expr },
+    /// `target` helykifejezés (azonosító, vagy azonosítóból / `self`-ből induló index- és mezőlánc);
+    /// `op` az összetett értékadás (`+=` …) művelete.
+    Assign { target: Expr, op: Option<BinOp>, value: Expr },
     Return(Option<Expr>),
     If { cond: Expr, then_block: Block, else_block: Option<Block> },
     While { cond: Expr, body: Block },
     ForIn { var: String, iter: Expr, body: Block },
+    /// `try { ... } catch (e) { ... }`: a törzs futásidejű hibájakor a kezelő fut, `e` a hiba
+    /// map-je (`code`, `message`).
+    Try { body: Block, var: String, handler: Block },
     Break,
     Continue,
     Expr(Expr),
 }
 
 #[derive(Debug, Clone)]
-pub enum Expr {
+pub struct Expr { pub kind: ExprKind, pub span: Span }
+
+#[derive(Debug, Clone)]
+pub enum ExprKind {
     Ident(String),
     Int(i64),
+    Float(f64),
     Str(String),
     Bool(bool),
+    Void,
     Array(Vec<Expr>),
+    /// `{kulcs: érték, ...}`: a kulcsok Int vagy Str értékű kifejezések.
+    Map(Vec<(Expr, Expr)>),
     Index { target: Box<Expr>, index: Box<Expr> },
+    /// `a[start:end]`: tömb- vagy stringszelet; a hiányzó határ az eleje, ill. a vége.
+    Slice { target: Box<Expr>, start: Option<Box<Expr>>, end: Option<Box<Expr>> },
+    Field { target: Box<Expr>, name: String },
+    SelfRef,
+    /// `super.method(args)`: a szülőosztály metódusa a jelenlegi `self`-fel.
+    SuperCall { method: String, args: Vec<Expr> },
     Call { callee: Box<Expr>, args: Vec<Expr> },
+    /// `fn(params) { ... }`: névtelen függvény, a környező változókat befogja.
+    Lambda { params: Vec<String>, body: Block },
     Binary { op: BinOp, left: Box<Expr>, right: Box<Expr> },
+    Unary { op: UnOp, expr: Box<Expr> },
     Group(Box<Expr>),
 }
 
 #[derive(Debug, Clone, Copy)]
-pub enum BinOp { Add, Sub, Mul, Div, Eq, Ne, Lt, Le, Gt, Ge }
+pub enum BinOp { Add, Sub, Mul, Div, Mod, Eq, Ne, Lt, Le, Gt, Ge, And, Or }
+
+#[derive(Debug, Clone, Copy)]
+pub enum UnOp { Neg, Not }
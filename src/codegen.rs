@@ -1,162 +1,666 @@
-use std::collections::HashMap;
-use anyhow::{anyhow, Result};
-use crate::ast::*; use crate::ir::*;
+use std::collections::{HashMap, HashSet};
+use crate::ast::*; use crate::error::{ResolveError, Result, RunaError}; use crate::ir::*; use crate::token::Span;
 
-pub struct Codegen { funcs: Vec<FunctionIR> }
+/// Egy forrásfájl a linkeléshez. `prefix`: a modul függvényeinek és osztályainak névtere az IR-ben
+/// (a belépő fájlnál üres); `builtins`: a fájl lokáléja szerinti beépített nevek; `imports`: a fájlban
+/// használható modulnév -> a modul indexe.
+pub struct Module { pub prefix: String, pub program: Program, pub builtins: HashMap<String, Builtin>, pub imports: HashMap<String, usize> }
+
+/// Egy modul nevei a kódgeneráláskor: saját függvények, osztályok (-> a `classes`-beli index),
+/// globális változók (a `globals`-beli indexszel), importok és beépítettek.
+struct Scope { prefix: String, fns: HashSet<String>, classes: HashMap<String, usize>, globals: HashMap<String, Binding>, imports: HashMap<String, usize>, builtins: HashMap<String, Builtin> }
+
+/// Egy változónév kötése: a lokális slot vagy globális index, `var`-e, és a deklaráció helye.
+#[derive(Clone, Copy)]
+struct Binding { slot: usize, mutable: bool, decl: Span }
+
+impl Scope {
+    fn qualify(&self, name: &str) -> String { if self.prefix.is_empty() { name.to_string() } else { format!("{}.{}", self.prefix, name) } }
+}
+
+/// A modulokat egyetlen programmá linkeli. `classes` eleme addig üres, amíg az osztály
+/// (az ősei után) el nem készül.
+#[derive(Default)]
+pub struct Codegen {
+    funcs: Vec<FunctionIR>,
+    classes: Vec<Option<ClassIR>>,
+    globals: Vec<String>,
+    scopes: Vec<Scope>,
+}
 impl Codegen {
-    pub fn new() -> Self { Self { funcs: Vec::new() } }
+    pub fn new() -> Self { Self::default() }
+
+    pub fn build(mut self, modules: &[Module]) -> Result<ProgramIR> {
+        let mut decls: Vec<(usize, &ClassDecl)> = Vec::new();
+        for (m, module) in modules.iter().enumerate() {
+            let mut scope = Scope { prefix: module.prefix.clone(), fns: HashSet::new(), classes: HashMap::new(), globals: HashMap::new(), imports: module.imports.clone(), builtins: module.builtins.clone() };
+            for it in &module.program.items {
+                match it {
+                    Item::Func(f) => { scope.fns.insert(f.name.clone()); }
+                    Item::Class(c) => {
+                        if scope.classes.contains_key(&c.name) { return Err(RunaError::Resolve { kind: ResolveError::DuplicateClass(c.name.clone()), span: c.span }); }
+                        scope.classes.insert(c.name.clone(), decls.len());
+                        decls.push((m, c));
+                    }
+                    Item::Let(d) => {
+                        if scope.globals.contains_key(&d.name) { return Err(RunaError::Resolve { kind: ResolveError::DuplicateGlobal(d.name.clone()), span: d.span }); }
+                        scope.globals.insert(d.name.clone(), Binding { slot: self.globals.len(), mutable: d.mutable, decl: d.span });
+                        self.globals.push(scope.qualify(&d.name));
+                    }
+                    Item::Import(_) => {}
+                }
+            }
+            self.scopes.push(scope);
+        }
+        for (m, module) in modules.iter().enumerate() {
+            for it in &module.program.items {
+                if let Item::Func(f) = it { self.gen_func(m, self.scopes[m].qualify(&f.name), f, None)?; }
+            }
+        }
+        self.classes = decls.iter().map(|_| None).collect();
+        for i in 0..decls.len() { self.gen_class(&decls, i, &mut Vec::new())?; }
+        let init = self.gen_init(modules)?;
+        Ok(ProgramIR { functions: self.funcs, classes: self.classes.into_iter().map(|c| c.expect("every class is generated")).collect(), globals: self.globals, init })
+    }
 
-    pub fn build(mut self, p: &Program) -> Result<ProgramIR> {
-        for it in &p.items {
-            match it {
-                Item::Func(f) => { self.gen_func(f)?; }
-                Item::Class(c) => { for m in &c.methods { let mut m2 = m.clone(); m2.name = format!("{}.{}", c.name, m.name); self.gen_func(&m2)?; } }
-                Item::Let(_) => {}
+    /// A `<init>` függvény a globálisok kezdőértékét állítja be: az importált modulokét az importáló
+    /// előtt, modulon belül a forrás sorrendjében. Kezdőértékben csak a már beállított globálisra
+    /// lehet hivatkozni; a kezdőértékből hívott függvényeket a VM ellenőrzi.
+    fn gen_init(&mut self, modules: &[Module]) -> Result<usize> {
+        let mut order = Vec::new();
+        init_order(modules, 0, &mut order);
+        let mut chunk = Chunk::new();
+        let mut local_count = 0;
+        let mut nested = Vec::new();
+        for m in order {
+            for it in &modules[m].program.items {
+                let Item::Let(d) = it else { continue };
+                let g = self.scopes[m].globals[&d.name].slot;
+                let mut cg = FnCG::new(format!("<init>.{}", self.globals[g]), &[], false, &self.scopes, m);
+                cg.init_limit = Some(g);
+                cg.expr(&d.init, &mut chunk)?;
+                chunk.push(Op::StoreGlobal(g), d.span);
+                local_count = local_count.max(cg.local_count());
+                nested.append(&mut cg.nested);
             }
         }
-        Ok(ProgramIR { functions: self.funcs })
+        chunk.push(Op::PushVoid, Span::default());
+        chunk.push(Op::Return, Span::default());
+        let init = self.funcs.len();
+        self.funcs.push(FunctionIR { name: "<init>".to_string(), arity: 0, method: false, local_count, chunk });
+        self.funcs.append(&mut nested);
+        Ok(init)
+    }
+
+    /// Az ős neve a modul saját osztálya, vagy `modul.Osztály` egy importált modulból.
+    fn resolve_class(&self, m: usize, name: &str) -> Option<usize> {
+        let scope = &self.scopes[m];
+        match name.split_once('.') {
+            Some((alias, class)) => scope.imports.get(alias).and_then(|&mi| self.scopes[mi].classes.get(class)).copied(),
+            None => scope.classes.get(name).copied(),
+        }
     }
 
-    fn gen_func(&mut self, f: &FuncDecl) -> Result<()> {
-        let mut cg = FnCG::new(&f.params);
+    /// Előbb az ős készül el (`visiting`: a folyamatban lévő leszármazási lánc, a körök felismeréséhez).
+    /// A metódusok `Class.method` nevű függvények lesznek rejtett `self` paraméterrel; a mezők
+    /// kezdőértékét a `Class.<fields>` függvény állítja be a konstruktor előtt, az ős mezőivel kezdve.
+    fn gen_class(&mut self, decls: &[(usize, &ClassDecl)], i: usize, visiting: &mut Vec<usize>) -> Result<()> {
+        if self.classes[i].is_some() { return Ok(()); }
+        let (m, c) = decls[i];
+        if visiting.contains(&i) { return Err(RunaError::Resolve { kind: ResolveError::InheritanceCycle(c.name.clone()), span: c.span }); }
+        let parent = match &c.parent {
+            Some((name, span)) => {
+                let pi = self.resolve_class(m, name).ok_or_else(|| RunaError::Resolve { kind: ResolveError::UnknownClass(name.clone()), span: *span })?;
+                visiting.push(i);
+                self.gen_class(decls, pi, visiting)?;
+                visiting.pop();
+                Some(pi)
+            }
+            None => None,
+        };
+        let class_name = self.scopes[m].qualify(&c.name);
+        let base = parent.and_then(|pi| self.classes[pi].as_ref());
+        let mut fields: Vec<String> = base.map(|b| b.fields.clone()).unwrap_or_default();
+        let mut mutable: Vec<bool> = base.map(|b| b.mutable.clone()).unwrap_or_default();
+        let (mut methods, mut ctor) = base.map(|b| (b.methods.clone(), b.ctor)).unwrap_or_default();
+        let parent_init = base.map(|b| self.funcs[b.init].name.clone());
+        for fd in &c.fields {
+            if fields.contains(&fd.name) { return Err(RunaError::Resolve { kind: ResolveError::DuplicateField(fd.name.clone()), span: fd.span }); }
+            fields.push(fd.name.clone());
+            mutable.push(fd.mutable);
+        }
+
+        let init = self.funcs.len();
+        let mut cg = FnCG::new(format!("{}.<fields>", class_name), &[], true, &self.scopes, m);
+        let mut chunk = Chunk::new();
+        if let Some(parent_init) = parent_init {
+            chunk.push(Op::LoadLocal(0), c.span);
+            chunk.push(Op::CallName(parent_init, 1), c.span);
+            chunk.push(Op::Pop, c.span);
+        }
+        for fd in &c.fields {
+            chunk.push(Op::LoadLocal(0), fd.span);
+            match &fd.init { Some(e) => cg.expr(e, &mut chunk)?, None => chunk.push(Op::PushVoid, fd.span) }
+            chunk.push(Op::InitField(fd.name.clone()), fd.span);
+        }
+        chunk.push(Op::PushVoid, c.span);
+        chunk.push(Op::Return, c.span);
+        let local_count = cg.local_count();
+        self.funcs.push(FunctionIR { name: cg.name.clone(), arity: 1, method: true, local_count, chunk });
+        self.funcs.append(&mut cg.nested);
+
+        // a leszármazott azonos nevű metódusa felülírja az örököltet; saját konstruktor nélkül az ősé marad
+        let fixed = self.let_fields(decls, i);
+        let own: HashSet<String> = c.fields.iter().filter(|fd| !fd.mutable).map(|fd| fd.name.clone()).collect();
+        for f in &c.methods {
+            let idx = self.funcs.len();
+            let own = if f.name == c.name { own.clone() } else { HashSet::new() };
+            self.gen_func(m, format!("{}.{}", class_name, f.name), f, Some(Method { parent, fixed: fixed.clone(), init: own }))?;
+            methods.insert(f.name.clone(), idx);
+            if f.name == c.name { ctor = Some(idx); }
+        }
+        self.classes[i] = Some(ClassIR { name: class_name, fields, mutable, methods, init, ctor });
+        Ok(())
+    }
+
+    /// Az osztály és ősei `let` mezői a deklarációjuk helyével (a körök ekkorra már kiszűrve).
+    fn let_fields(&self, decls: &[(usize, &ClassDecl)], mut i: usize) -> HashMap<String, Span> {
+        let mut fixed = HashMap::new();
+        loop {
+            let (m, c) = decls[i];
+            fixed.extend(c.fields.iter().filter(|fd| !fd.mutable).map(|fd| (fd.name.clone(), fd.span)));
+            match c.parent.as_ref().and_then(|(name, _)| self.resolve_class(m, name)) {
+                Some(pi) => i = pi,
+                None => return fixed,
+            }
+        }
+    }
+
+    /// `name`: a függvény teljes (névtérrel ellátott) neve; `method`: metódusnál az osztálytól kapott adatok.
+    fn gen_func(&mut self, m: usize, name: String, f: &FuncDecl, method: Option<Method>) -> Result<()> {
+        if self.funcs.iter().any(|g| g.name == name) { return Err(RunaError::Resolve { kind: ResolveError::DuplicateFunction(f.name.clone()), span: f.span }); }
+        let mut cg = FnCG::new(name.clone(), &f.params, method.is_some(), &self.scopes, m);
+        let method = match method {
+            Some(Method { parent, fixed, init }) => { cg.parent = parent; cg.fixed = fixed; cg.init = init; true }
+            None => false,
+        };
         let mut chunk = Chunk::new();
         cg.block(&f.body, &mut chunk)?;
-        chunk.code.push(Op::PushVoid);
-        chunk.code.push(Op::Return);
-        let func = FunctionIR { name: f.name.clone(), arity: f.params.len(), local_count: cg.local_count(), chunk };
+        chunk.push(Op::PushVoid, f.body.span);
+        chunk.push(Op::Return, f.body.span);
+        let func = FunctionIR { name, arity: f.params.len() + method as usize, method, local_count: cg.local_count(), chunk };
         self.funcs.push(func);
+        self.funcs.append(&mut cg.nested);
         Ok(())
     }
 }
 
-struct LoopCtx { start: usize, breaks: Vec<usize>, continues: Vec<usize> }
+/// A modulok kezdőértékadási sorrendje: mélységi bejárás, az importok a modul előtt (betöltési sorrendben).
+fn init_order(modules: &[Module], m: usize, order: &mut Vec<usize>) {
+    let mut deps: Vec<usize> = modules[m].imports.values().copied().collect();
+    deps.sort_unstable();
+    for d in deps { if !order.contains(&d) { init_order(modules, d, order); } }
+    order.push(m);
+}
+
+/// Metódus generálásához: `parent` az osztály őse, a `super` hívásokhoz; `fixed` az osztály és
+/// ősei `let` mezői; `init` konstruktornál az osztály saját `let` mezői, egyébként üres.
+struct Method { parent: Option<usize>, fixed: HashMap<String, Span>, init: HashSet<String> }
+
+/// `tries`: a ciklus előtt nyitott `try` blokkok száma; a kilépő `break`/`continue` a cikluson
+/// belül nyitottakat zárja le.
+struct LoopCtx { start: usize, breaks: Vec<usize>, continues: Vec<usize>, tries: usize }
+
+/// A befogható `self` neve a belső függvények környezetében (azonosító nem lehet ilyen).
+const SELF: &str = "<self>";
 
 struct FnCG<'a> {
-    locals: HashMap<String, usize>,
+    /// A generált függvény teljes neve; a benne lévő névtelen függvények `név.<fn#N>` nevet kapnak.
+    name: String,
+    /// Blokkhatókörök veremként, a legbelső a végén; a függvény paraméterei az elsőben.
+    blocks: Vec<BlockScope>,
     next_local: usize,
-    _params: &'a [String],
+    max_local: usize,
+    /// Névtelen függvényben: a környező függvények látható változói, amelyeket befoghat.
+    enclosing: HashMap<String, Binding>,
+    /// A befogott nevek; az indexük a closure upvalue-listájában.
+    upvalues: Vec<String>,
+    lambdas: usize,
+    /// A függvényben (közvetve) definiált névtelen függvények.
+    nested: Vec<FunctionIR>,
+    /// Metódusban a fogadó helye (mindig a 0. lokális).
+    self_slot: Option<usize>,
+    parent: Option<usize>,
+    /// Metódusban: az osztály `let` mezői; `self.mező = ...` ezekre tilos.
+    fixed: HashMap<String, Span>,
+    /// Konstruktorban: az osztály saját `let` mezői, ezeket `self.mező = ...` beállíthatja
+    /// (a benne definiált névtelen függvények nem).
+    init: HashSet<String>,
+    /// Az összes modul nevei; `module` a jelenlegi függvényt tartalmazó modul.
+    scopes: &'a [Scope],
+    module: usize,
+    /// Globális kezdőértékének generálásakor: a modul ettől az indextől kezdődő globálisai még nincsenek beállítva.
+    init_limit: Option<usize>,
     loops: Vec<LoopCtx>,
+    /// A nyitott `try` blokkok száma.
+    tries: usize,
 }
 impl<'a> FnCG<'a> {
-    fn new(params: &'a [String]) -> Self {
-        let mut cg = Self { locals: HashMap::new(), next_local: 0, _params: params, loops: Vec::new() };
-        for (i, name) in params.iter().enumerate() { cg.locals.insert(name.clone(), i); cg.next_local = cg.next_local.max(i + 1); }
+    fn new(name: String, params: &[String], method: bool, scopes: &'a [Scope], module: usize) -> Self {
+        let first = method as usize;
+        let mut cg = Self {
+            name, blocks: vec![BlockScope { names: HashMap::new(), first_slot: first }], next_local: first, max_local: first,
+            enclosing: HashMap::new(), upvalues: Vec::new(), lambdas: 0, nested: Vec::new(),
+            self_slot: method.then_some(0), parent: None, fixed: HashMap::new(), init: HashSet::new(), scopes, module, init_limit: None, loops: Vec::new(), tries: 0,
+        };
+        for name in params { cg.declare(name, true, Span::default()); }
         cg
     }
-    fn local_count(&self) -> usize { self.next_local }
-    fn get_local(&self, name: &str) -> Option<usize> { self.locals.get(name).copied() }
-    fn alloc_local(&mut self, name: &str) -> usize { if let Some(&i) = self.locals.get(name) { i } else { let i = self.next_local; self.locals.insert(name.to_string(), i); self.next_local += 1; i } }
+    /// A `self` metódusban a 0. lokális, metódusbeli névtelen függvényben befogott változó.
+    fn self_var(&mut self, span: Span) -> Result<Var> {
+        if let Some(slot) = self.self_slot { return Ok(Var::Local(slot)); }
+        if self.enclosing.contains_key(SELF) { return Ok(Var::Upvalue(self.upvalue(SELF))); }
+        Err(RunaError::Resolve { kind: ResolveError::SelfOutsideMethod, span })
+    }
+    fn local_count(&self) -> usize { self.max_local }
+    /// A legbelső látható kötés.
+    fn get_local(&self, name: &str) -> Option<Binding> { self.blocks.iter().rev().find_map(|b| b.names.get(name)).copied() }
+    fn get_global(&self, name: &str, span: Span) -> Result<Option<Binding>> {
+        match self.scopes[self.module].globals.get(name) {
+            Some(g) if self.init_limit.is_some_and(|limit| g.slot >= limit) => Err(RunaError::Resolve { kind: ResolveError::GlobalBeforeInit(name.to_string()), span }),
+            g => Ok(g.copied()),
+        }
+    }
+    /// Változó feloldása: saját lokális, befogott (környező függvénybeli), végül a modul globálisa.
+    fn lookup(&mut self, name: &str, span: Span) -> Result<Option<(Var, Binding)>> {
+        if let Some(b) = self.get_local(name) { return Ok(Some((Var::Local(b.slot), b))); }
+        if let Some(&b) = self.enclosing.get(name) { return Ok(Some((Var::Upvalue(self.upvalue(name)), b))); }
+        Ok(self.get_global(name, span)?.map(|g| (Var::Global(g.slot), g)))
+    }
+    fn upvalue(&mut self, name: &str) -> usize {
+        match self.upvalues.iter().position(|n| n == name) {
+            Some(i) => i,
+            None => { self.upvalues.push(name.to_string()); self.upvalues.len() - 1 }
+        }
+    }
+    /// A belső függvény által befogott név forrása ebben a függvényben (szükség esetén ez is befogja).
+    fn capture(&mut self, name: &str) -> Capture {
+        match (self.get_local(name), self.self_slot) {
+            (Some(b), _) => Capture::Local(b.slot),
+            (None, Some(slot)) if name == SELF => Capture::Local(slot),
+            _ => Capture::Upvalue(self.upvalue(name)),
+        }
+    }
+    /// A belső függvény számára látható változók; a belső blokkok kötései elfedik a külsőket.
+    fn visible(&self) -> HashMap<String, Binding> {
+        let mut names = self.enclosing.clone();
+        if let Some(slot) = self.self_slot { names.insert(SELF.to_string(), Binding { slot, mutable: false, decl: Span::default() }); }
+        for b in &self.blocks { names.extend(b.names.iter().map(|(n, b)| (n.clone(), *b))); }
+        names
+    }
+    /// Importált modul, ha a nevet nem takarja el helyi, befogott vagy globális változó.
+    fn module_alias(&self, name: &str) -> Option<usize> {
+        let scope = &self.scopes[self.module];
+        if self.get_local(name).is_some() || self.enclosing.contains_key(name) || scope.globals.contains_key(name) { return None; }
+        scope.imports.get(name).copied()
+    }
+    fn alloc_slot(&mut self) -> usize {
+        self.next_local += 1;
+        self.max_local = self.max_local.max(self.next_local);
+        self.next_local - 1
+    }
+    /// Új kötés friss slottal a legbelső blokkban; elfedi a kívül (vagy korábban) azonos néven deklaráltat.
+    fn declare(&mut self, name: &str, mutable: bool, decl: Span) -> usize {
+        let slot = self.alloc_slot();
+        self.blocks.last_mut().expect("the function scope is never popped").names.insert(name.to_string(), Binding { slot, mutable, decl });
+        slot
+    }
+    fn enter_block(&mut self) { self.blocks.push(BlockScope { names: HashMap::new(), first_slot: self.next_local }); }
+    /// A blokk kötései megszűnnek, a slotjai újra kioszthatók.
+    fn exit_block(&mut self) { let b = self.blocks.pop().expect("unbalanced block scope"); self.next_local = b.first_slot; }
 
-    fn block(&mut self, b: &Block, out: &mut Chunk) -> Result<()> { for s in &b.stmts { self.stmt(s, out)?; } Ok(()) }
+    fn block(&mut self, b: &Block, out: &mut Chunk) -> Result<()> {
+        self.enter_block();
+        for s in &b.stmts { self.stmt(s, out)?; }
+        self.exit_block();
+        Ok(())
+    }
 
     fn stmt(&mut self, s: &Stmt, out: &mut Chunk) -> Result<()> {
-        match s {
-            Stmt::Let(d) => { self.expr(&d.init, out)?; let idx = self.alloc_local(&d.name); out.code.push(Op::StoreLocal(idx)); }
-            Stmt::Assign { name, value } => {
-                self.expr(value, out)?; let idx = self.get_local(name).ok_or_else(|| anyhow!(format!("Értékadás előtt nincs változó: {}", name)))?;
-                out.code.push(Op::StoreLocal(idx));
-            }
-            Stmt::Return(None) => { out.code.push(Op::PushVoid); out.code.push(Op::Return); }
-            Stmt::Return(Some(e)) => { self.expr(e, out)?; out.code.push(Op::Return); }
-            Stmt::If { cond, then_block, else_block } => {
-                self.expr(cond, out)?; let jf = out.code.len(); out.code.push(Op::JumpIfFalse(usize::MAX));
+        match &s.kind {
+            StmtKind::Let(d) => { self.expr(&d.init, out)?; let idx = self.declare(&d.name, d.mutable, d.span); out.push(Op::InitLocal(idx), s.span); }
+            StmtKind::Assign { target, op, value } => self.assign(target, *op, value, s.span, out)?,
+            StmtKind::Return(None) => { out.push(Op::PushVoid, s.span); out.push(Op::Return, s.span); }
+            StmtKind::Return(Some(e)) => { self.expr(e, out)?; out.push(Op::Return, s.span); }
+            StmtKind::If { cond, then_block, else_block } => {
+                self.expr(cond, out)?; let jf = out.code.len(); out.push(Op::JumpIfFalse(usize::MAX), s.span);
                 self.block(then_block, out)?;
                 if let Some(else_b) = else_block {
-                    let je = out.code.len(); out.code.push(Op::Jump(usize::MAX));
+                    let je = out.code.len(); out.push(Op::Jump(usize::MAX), s.span);
                     out.code[jf] = Op::JumpIfFalse(out.code.len());
                     self.block(else_b, out)?;
                     out.code[je] = Op::Jump(out.code.len());
                 } else { out.code[jf] = Op::JumpIfFalse(out.code.len()); }
             }
-            Stmt::While { cond, body } => {
+            StmtKind::While { cond, body } => {
                 let start = out.code.len();
-                self.expr(cond, out)?; let jf = out.code.len(); out.code.push(Op::JumpIfFalse(usize::MAX));
-                self.loops.push(LoopCtx { start, breaks: Vec::new(), continues: Vec::new() });
+                self.expr(cond, out)?; let jf = out.code.len(); out.push(Op::JumpIfFalse(usize::MAX), s.span);
+                self.loops.push(LoopCtx { start, breaks: Vec::new(), continues: Vec::new(), tries: self.tries });
                 self.block(body, out)?;
-                out.code.push(Op::Jump(start));
+                out.push(Op::Jump(start), s.span);
                 let end = out.code.len();
                 out.code[jf] = Op::JumpIfFalse(end);
                 let lp = self.loops.pop().unwrap();
                 for bpos in lp.breaks { out.code[bpos] = Op::Jump(end); }
-for cpos in lp.continues { out.code[cpos] = Op::Jump(lp.start); }
+                for cpos in lp.continues { out.code[cpos] = Op::Jump(lp.start); }
             }
-            Stmt::ForIn { var, iter, body } => {
-                self.expr(iter, out)?; let arr_local = self.alloc_local("__for_arr"); out.code.push(Op::StoreLocal(arr_local));
-                let idx_local = self.alloc_local("__for_i"); out.code.push(Op::PushInt(0)); out.code.push(Op::StoreLocal(idx_local));
+            StmtKind::ForIn { var, iter, body } => {
+                self.expr(iter, out)?;
+                out.push(Op::Iter, iter.span);
+                // a rejtett tömb- és indexslot, valamint a ciklusváltozó a ciklus saját hatókörében él
+                self.enter_block();
+                let arr_local = self.alloc_slot(); out.push(Op::InitLocal(arr_local), s.span);
+                let idx_local = self.alloc_slot(); out.push(Op::PushInt(0), s.span); out.push(Op::InitLocal(idx_local), s.span);
                 let start = out.code.len();
-                out.code.push(Op::LoadLocal(idx_local));
-                out.code.push(Op::LoadLocal(arr_local));
-                out.code.push(Op::CallName("len".to_string(), 1));
-                out.code.push(Op::Lt);
-                let jf = out.code.len(); out.code.push(Op::JumpIfFalse(usize::MAX));
-                self.loops.push(LoopCtx { start, breaks: Vec::new(), continues: Vec::new() });
-                let v_local = self.alloc_local(var);
-                out.code.push(Op::LoadLocal(arr_local));
-                out.code.push(Op::LoadLocal(idx_local));
-                out.code.push(Op::IndexGet);
-                out.code.push(Op::StoreLocal(v_local));
+                out.push(Op::LoadLocal(idx_local), s.span);
+                out.push(Op::LoadLocal(arr_local), s.span);
+                out.push(Op::CallBuiltin(Builtin::Len, 1), s.span);
+                out.push(Op::Lt, s.span);
+                let jf = out.code.len(); out.push(Op::JumpIfFalse(usize::MAX), s.span);
+                self.loops.push(LoopCtx { start, breaks: Vec::new(), continues: Vec::new(), tries: self.tries });
+                let v_local = self.declare(var, true, s.span);
+                out.push(Op::LoadLocal(arr_local), s.span);
+                out.push(Op::LoadLocal(idx_local), s.span);
+                out.push(Op::IndexGet, s.span);
+                // minden kör új kötés: a korábbi körben létrehozott closure a saját értékét látja
+                out.push(Op::InitLocal(v_local), s.span);
                 self.block(body, out)?;
                 let cont_jump_pos = out.code.len();
-                out.code.push(Op::LoadLocal(idx_local));
-                out.code.push(Op::PushInt(1));
-                out.code.push(Op::Add);
-                out.code.push(Op::StoreLocal(idx_local));
-                out.code.push(Op::Jump(start));
+                out.push(Op::LoadLocal(idx_local), s.span);
+                out.push(Op::PushInt(1), s.span);
+                out.push(Op::Add, s.span);
+                out.push(Op::StoreLocal(idx_local), s.span);
+                out.push(Op::Jump(start), s.span);
                 let end = out.code.len();
                 out.code[jf] = Op::JumpIfFalse(end);
                 let lp = self.loops.pop().unwrap();
                 for bpos in lp.breaks { out.code[bpos] = Op::Jump(end); }
-for cpos in lp.continues { out.code[cpos] = Op::Jump(lp.start); }
+                for cpos in lp.continues { out.code[cpos] = Op::Jump(cont_jump_pos); }
+                self.exit_block();
+            }
+            StmtKind::Try { body, var, handler } => {
+                let begin = out.code.len(); out.push(Op::TryBegin(usize::MAX), s.span);
+                self.tries += 1;
+                self.block(body, out)?;
+                self.tries -= 1;
+                out.push(Op::TryEnd, s.span);
+                let jump = out.code.len(); out.push(Op::Jump(usize::MAX), s.span);
+                out.code[begin] = Op::TryBegin(out.code.len());
+                self.enter_block();
+                let slot = self.declare(var, true, s.span);
+                out.push(Op::InitLocal(slot), s.span);
+                self.block(handler, out)?;
+                self.exit_block();
+                out.code[jump] = Op::Jump(out.code.len());
             }
-            Stmt::Break => {
-                if let Some(lp) = self.loops.last_mut() { let pos = out.code.len(); out.code.push(Op::Jump(usize::MAX)); lp.breaks.push(pos); }
-                else { return Err(anyhow!("break: nincs ciklusban")); }
+            StmtKind::Break => {
+                for _ in self.loops.last().map_or(0, |lp| lp.tries)..self.tries { out.push(Op::TryEnd, s.span); }
+                if let Some(lp) = self.loops.last_mut() { let pos = out.code.len(); out.push(Op::Jump(usize::MAX), s.span); lp.breaks.push(pos); }
+                else { return Err(RunaError::Resolve { kind: ResolveError::BreakOutsideLoop, span: s.span }); }
             }
-            Stmt::Continue => {
-                if let Some(lp) = self.loops.last_mut() { let pos = out.code.len(); out.code.push(Op::Jump(usize::MAX)); lp.continues.push(pos); }
-                else { return Err(anyhow!("continue: nincs ciklusban")); }
+            StmtKind::Continue => {
+                for _ in self.loops.last().map_or(0, |lp| lp.tries)..self.tries { out.push(Op::TryEnd, s.span); }
+                if let Some(lp) = self.loops.last_mut() { let pos = out.code.len(); out.push(Op::Jump(usize::MAX), s.span); lp.continues.push(pos); }
+                else { return Err(RunaError::Resolve { kind: ResolveError::ContinueOutsideLoop, span: s.span }); }
             }
-            Stmt::Expr(e) => { self.expr(e, out)?; out.code.push(Op::Pop); }
+            StmtKind::Expr(e) => { self.expr(e, out)?; out.push(Op::Pop, s.span); }
         }
         Ok(())
     }
 
+    /// `a[i].f[j] op= v`: a gyökér betöltése, a közbülső szintek kiolvasása (IndexGet / GetField),
+    /// majd a legbelső helyre IndexSet / SetField. A tömbök, map-ek és objektumok referenciák, így
+    /// a gyökeret nem kell visszaírni; összetett értékadásnál az utolsó index egyszer értékelődik ki.
+    fn assign(&mut self, target: &Expr, op: Option<BinOp>, value: &Expr, span: Span, out: &mut Chunk) -> Result<()> {
+        let mut steps = Vec::new();
+        let mut root = target;
+        loop {
+            match &root.kind {
+                ExprKind::Index { target, index } => { steps.push(Step::Index(index)); root = target; }
+                ExprKind::Field { target, name } => { steps.push(Step::Field(name)); root = target; }
+                _ => break,
+            }
+        }
+        steps.reverse();
+        let (var, binding) = match &root.kind {
+            ExprKind::Ident(name) => match (self.lookup(name, root.span)?, self.module_alias(name)) {
+                (Some((var, b)), _) => (var, Some((name.clone(), b))),
+                // `modul.g = ...`: az importált modul globálisa
+                (None, Some(mi)) if matches!(steps.first(), Some(Step::Field(_))) => {
+                    let Step::Field(g) = steps.remove(0) else { unreachable!() };
+                    let qualified = format!("{}.{}", name, g);
+                    let g = *self.scopes[mi].globals.get(g).ok_or_else(|| RunaError::Resolve { kind: ResolveError::UnknownIdent(qualified.clone()), span: root.span })?;
+                    (Var::Global(g.slot), Some((qualified, g)))
+                }
+                _ => return Err(RunaError::Resolve { kind: ResolveError::AssignUndeclared(name.clone()), span: root.span }),
+            },
+            ExprKind::SelfRef => (self.self_var(root.span)?, None),
+            _ => unreachable!("the parser only accepts identifier- or self-rooted places"),
+        };
+        // csak a kötés cseréje tilos; a `let` tömb elemei, objektum mezői írhatók
+        if let Some((name, b)) = binding.filter(|(_, b)| steps.is_empty() && !b.mutable) {
+            return Err(RunaError::Resolve { kind: ResolveError::AssignImmutable { name, decl: b.decl }, span });
+        }
+        // `self.mező = ...` a `let` mezőre csak a saját konstruktorban; más objektum mezőjét a VM ellenőrzi
+        let mut init = false;
+        if let (ExprKind::SelfRef, [Step::Field(name)]) = (&root.kind, steps.as_slice()) {
+            init = self.init.contains(*name);
+            if let Some(&decl) = self.fixed.get(*name).filter(|_| !init) {
+                return Err(RunaError::Resolve { kind: ResolveError::AssignImmutable { name: name.to_string(), decl }, span });
+            }
+        }
+
+        let Some((last, inner)) = steps.split_last() else {
+            if op.is_some() { out.push(var.load(), span); }
+            self.expr(value, out)?;
+            if let Some(op) = op { out.push(binop(op), span); }
+            out.push(var.store(), span);
+            return Ok(());
+        };
+        out.push(var.load(), span);
+        for step in inner {
+            match step {
+                Step::Index(index) => { self.expr(index, out)?; out.push(Op::IndexGet, span); }
+                Step::Field(name) => out.push(Op::GetField(name.to_string()), span),
+            }
+        }
+        match last {
+            Step::Index(index) => {
+                self.expr(index, out)?;
+                if op.is_some() { out.push(Op::Dup2, span); out.push(Op::IndexGet, span); }
+            }
+            Step::Field(name) => if op.is_some() { out.push(Op::Dup, span); out.push(Op::GetField(name.to_string()), span); },
+        }
+        self.expr(value, out)?;
+        if let Some(op) = op { out.push(binop(op), span); }
+        out.push(match last {
+            Step::Index(_) => Op::IndexSet,
+            Step::Field(name) if init => Op::InitField(name.to_string()),
+            Step::Field(name) => Op::SetField(name.to_string()),
+        }, span);
+        Ok(())
+    }
+
     fn expr(&mut self, e: &Expr, out: &mut Chunk) -> Result<()> {
-        match e {
-            Expr::Ident(name) => {
-                if let Some(&idx) = self.locals.get(name) { out.code.push(Op::LoadLocal(idx)); }
-                else { return Err(anyhow!(format!("Ismeretlen azonosító: {}", name))); }
-            }
-            Expr::Int(n) => out.code.push(Op::PushInt(*n)),
-            Expr::Str(s) => out.code.push(Op::PushStr(s.clone())),
-            Expr::Bool(b) => out.code.push(Op::PushBool(*b)),
-            Expr::Array(elems) => { for el in elems { self.expr(el, out)?; } out.code.push(Op::MakeArray(elems.len())); }
-            Expr::Index { target, index } => { self.expr(target, out)?; self.expr(index, out)?; out.code.push(Op::IndexGet); }
-            Expr::Group(inner) => self.expr(inner, out)?,
-            Expr::Binary { op, left, right } => {
+        match &e.kind {
+            ExprKind::Ident(name) => match self.lookup(name, e.span)? {
+                Some((var, _)) => out.push(var.load(), e.span),
+                // a modul függvénye mint érték
+                None if self.scopes[self.module].fns.contains(name) => out.push(Op::MakeClosure(self.scopes[self.module].qualify(name), Vec::new()), e.span),
+                None if self.scopes[self.module].builtins.contains_key(name) => out.push(Op::PushBuiltin(self.scopes[self.module].builtins[name]), e.span),
+                None => return Err(RunaError::Resolve { kind: ResolveError::UnknownIdent(name.clone()), span: e.span }),
+            },
+            ExprKind::Int(n) => out.push(Op::PushInt(*n), e.span),
+            ExprKind::Float(x) => out.push(Op::PushFloat(*x), e.span),
+            ExprKind::Str(s) => out.push(Op::PushStr(s.clone()), e.span),
+            ExprKind::Bool(b) => out.push(Op::PushBool(*b), e.span),
+            ExprKind::Void => out.push(Op::PushVoid, e.span),
+            ExprKind::Array(elems) => { for el in elems { self.expr(el, out)?; } out.push(Op::MakeArray(elems.len()), e.span); }
+            ExprKind::Map(entries) => {
+                for (k, v) in entries { self.expr(k, out)?; self.expr(v, out)?; }
+                out.push(Op::MakeMap(entries.len()), e.span);
+            }
+            ExprKind::Index { target, index } => { self.expr(target, out)?; self.expr(index, out)?; out.push(Op::IndexGet, e.span); }
+            ExprKind::Slice { target, start, end } => {
+                self.expr(target, out)?;
+                for bound in [start, end] {
+                    match bound { Some(b) => self.expr(b, out)?, None => out.push(Op::PushVoid, e.span) }
+                }
+                out.push(Op::Slice, e.span);
+            }
+            ExprKind::Field { target, name } => {
+                // `modul.g`: az importált modul globálisa vagy függvénye
+                if let Some((alias, mi)) = match &target.kind { ExprKind::Ident(alias) => self.module_alias(alias).map(|mi| (alias, mi)), _ => None } {
+                    let scope = &self.scopes[mi];
+                    if let Some(g) = scope.globals.get(name) { out.push(Op::LoadGlobal(g.slot), e.span); }
+                    else if scope.fns.contains(name) { out.push(Op::MakeClosure(scope.qualify(name), Vec::new()), e.span); }
+                    else { return Err(RunaError::Resolve { kind: ResolveError::UnknownIdent(format!("{}.{}", alias, name)), span: e.span }); }
+                } else {
+                    self.expr(target, out)?;
+                    out.push(Op::GetField(name.clone()), e.span);
+                }
+            }
+            ExprKind::SelfRef => { let var = self.self_var(e.span)?; out.push(var.load(), e.span); }
+            ExprKind::SuperCall { method, args } => {
+                let Some(parent) = self.parent else { return Err(RunaError::Resolve { kind: ResolveError::SuperWithoutParent, span: e.span }) };
+                let var = self.self_var(e.span)?;
+                out.push(var.load(), e.span);
+                for a in args { self.expr(a, out)?; }
+                out.push(Op::CallSuper(parent, method.clone(), args.len()), e.span);
+            }
+            ExprKind::Group(inner) => self.expr(inner, out)?,
+            // rövidzár: a jobb oldal csak akkor fut, ha a bal nem dönti el az eredményt
+            // mindkét operandus Bool kell legyen, a jobb oldalé is (`false || 7` hiba)
+            ExprKind::Binary { op: BinOp::And, left, right } => {
+                self.expr(left, out)?; out.push(Op::CheckBool("&&"), e.span);
+                let jf = out.code.len(); out.push(Op::JumpIfFalse(usize::MAX), e.span);
+                self.expr(right, out)?; out.push(Op::CheckBool("&&"), e.span);
+                let je = out.code.len(); out.push(Op::Jump(usize::MAX), e.span);
+                out.code[jf] = Op::JumpIfFalse(out.code.len());
+                out.push(Op::PushBool(false), e.span);
+                out.code[je] = Op::Jump(out.code.len());
+            }
+            ExprKind::Binary { op: BinOp::Or, left, right } => {
+                self.expr(left, out)?; out.push(Op::CheckBool("||"), e.span);
+                let jf = out.code.len(); out.push(Op::JumpIfFalse(usize::MAX), e.span);
+                out.push(Op::PushBool(true), e.span); let je = out.code.len(); out.push(Op::Jump(usize::MAX), e.span);
+                out.code[jf] = Op::JumpIfFalse(out.code.len());
+                self.expr(right, out)?; out.push(Op::CheckBool("||"), e.span);
+                out.code[je] = Op::Jump(out.code.len());
+            }
+            ExprKind::Binary { op, left, right } => {
                 self.expr(left, out)?; self.expr(right, out)?;
+                out.push(binop(*op), e.span);
+            }
+            ExprKind::Unary { op, expr } => {
+                self.expr(expr, out)?;
                 match op {
-                    BinOp::Add => out.code.push(Op::Add),
-                    BinOp::Sub => out.code.push(Op::Sub),
-                    BinOp::Mul => out.code.push(Op::Mul),
-                    BinOp::Div => out.code.push(Op::Div),
-                    BinOp::Eq  => out.code.push(Op::Eq),
-                    BinOp::Ne  => out.code.push(Op::Ne),
-                    BinOp::Lt  => out.code.push(Op::Lt),
-                    BinOp::Le  => out.code.push(Op::Le),
-                    BinOp::Gt  => out.code.push(Op::Gt),
-                    BinOp::Ge  => out.code.push(Op::Ge),
+                    UnOp::Neg => out.push(Op::Neg, e.span),
+                    UnOp::Not => out.push(Op::Not, e.span),
                 }
             }
-            Expr::Call { callee, args } => {
-                let name = match &**callee { Expr::Ident(n) => n.clone(), _ => return Err(anyhow!("Csak név alapú hívás")) };
+            ExprKind::Lambda { params, body } => {
+                self.lambdas += 1;
+                let name = format!("{}.<fn#{}>", self.name, self.lambdas);
+                let mut cg = FnCG::new(name.clone(), params, false, self.scopes, self.module);
+                cg.enclosing = self.visible();
+                cg.parent = self.parent;
+                cg.fixed = self.fixed.clone();
+                let mut chunk = Chunk::new();
+                cg.block(body, &mut chunk)?;
+                chunk.push(Op::PushVoid, body.span);
+                chunk.push(Op::Return, body.span);
+                let captures = cg.upvalues.iter().map(|n| self.capture(n)).collect();
+                self.nested.push(FunctionIR { name: name.clone(), arity: params.len(), method: false, local_count: cg.local_count(), chunk });
+                self.nested.append(&mut cg.nested);
+                out.push(Op::MakeClosure(name, captures), e.span);
+            }
+            ExprKind::Call { callee, args } => {
+                // `modul.f(...)`: ha a név nem helyi változó, hanem importált modul
+                if let ExprKind::Field { target, name } = &callee.kind {
+                    if let ExprKind::Ident(alias) = &target.kind {
+                        if let Some(mi) = self.module_alias(alias) {
+                            let scope = &self.scopes[mi];
+                            if let Some(g) = scope.globals.get(name) {
+                                out.push(Op::LoadGlobal(g.slot), callee.span);
+                                for a in args { self.expr(a, out)?; }
+                                out.push(Op::CallValue(args.len()), e.span);
+                                return Ok(());
+                            }
+                            for a in args { self.expr(a, out)?; }
+                            if scope.fns.contains(name) { out.push(Op::CallName(scope.qualify(name), args.len()), e.span); }
+                            else if let Some(&c) = scope.classes.get(name) { out.push(Op::New(c, args.len()), e.span); }
+                            else { return Err(RunaError::Resolve { kind: ResolveError::UnknownFunction(format!("{}.{}", alias, name)), span: callee.span }); }
+                            return Ok(());
+                        }
+                    }
+                }
+                if let ExprKind::Field { target, name } = &callee.kind {
+                    self.expr(target, out)?;
+                    for a in args { self.expr(a, out)?; }
+                    out.push(Op::CallMethod(name.clone(), args.len()), e.span);
+                    return Ok(());
+                }
+                // változóban tárolt vagy tetszőleges kifejezés adta függvényérték
+                let name = match &callee.kind {
+                    ExprKind::Ident(n) if self.lookup(n, callee.span)?.is_none() => n.clone(),
+                    _ => {
+                        self.expr(callee, out)?;
+                        for a in args { self.expr(a, out)?; }
+                        out.push(Op::CallValue(args.len()), e.span);
+                        return Ok(());
+                    }
+                };
                 for a in args { self.expr(a, out)?; }
-                out.code.push(Op::CallName(name, args.len()));
+                // saját függvény elfedi az azonos nevű beépítettet
+                let scope = &self.scopes[self.module];
+                if scope.fns.contains(&name) { out.push(Op::CallName(scope.qualify(&name), args.len()), e.span); }
+                else if let Some(&c) = scope.classes.get(&name) { out.push(Op::New(c, args.len()), e.span); }
+                else if let Some(&b) = scope.builtins.get(&name) { out.push(Op::CallBuiltin(b, args.len()), e.span); }
+                else { return Err(RunaError::Resolve { kind: ResolveError::UnknownFunction(name), span: callee.span }); }
             }
         }
         Ok(())
     }
 }
+enum Step<'e> { Index(&'e Expr), Field(&'e str) }
+
+#[derive(Clone, Copy)]
+enum Var { Local(usize), Upvalue(usize), Global(usize) }
+impl Var {
+    fn load(self) -> Op { match self { Var::Local(i) => Op::LoadLocal(i), Var::Upvalue(i) => Op::LoadUpvalue(i), Var::Global(i) => Op::LoadGlobal(i) } }
+    fn store(self) -> Op { match self { Var::Local(i) => Op::StoreLocal(i), Var::Upvalue(i) => Op::StoreUpvalue(i), Var::Global(i) => Op::StoreGlobal(i) } }
+}
+
+struct BlockScope { names: HashMap<String, Binding>, first_slot: usize }
+
+fn binop(op: BinOp) -> Op {
+    match op {
+        BinOp::Add => Op::Add,
+        BinOp::Sub => Op::Sub,
+        BinOp::Mul => Op::Mul,
+        BinOp::Div => Op::Div,
+        BinOp::Mod => Op::Mod,
+        BinOp::Eq  => Op::Eq,
+        BinOp::Ne  => Op::Ne,
+        BinOp::Lt  => Op::Lt,
+        BinOp::Le  => Op::Le,
+        BinOp::Gt  => Op::Gt,
+        BinOp::Ge  => Op::Ge,
+        BinOp::And | BinOp::Or => unreachable!("short-circuit operators are compiled to jumps"),
+    }
+}
+
 #[allow(dead_code)]
 impl Codegen {
     pub fn local_count(&self, fn_name: &str) -> usize {
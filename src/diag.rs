@@ -0,0 +1,33 @@
+use crate::token::Span;
+
+/// A span sorának kiírása, alatta `^^^` jelöléssel (többsoros span esetén az első sor végéig).
+pub fn snippet(src: &str, span: Span) -> String {
+    let line_start = src[..span.start.min(src.len())].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line_end = src[line_start..].find('\n').map(|i| line_start + i).unwrap_or(src.len());
+    let text = src[line_start..line_end].trim_end_matches('\r');
+    let start = span.start.clamp(line_start, line_start + text.len());
+    let end = span.end.clamp(start, line_start + text.len());
+
+    // tabokat megtartjuk, hogy a jelölés a sor alá igazodjon
+    let pad: String = src[line_start..start].chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+    let width = src[start..end].chars().count().max(1);
+    let gutter = " ".repeat(span.line.to_string().len());
+    format!("{} |\n{} | {}\n{} | {}{}\n", gutter, span.line, text, gutter, pad, "^".repeat(width))
+}
+
+/// Sorkezdetek táblázata, bájt-pozícióból sor/oszlop számításhoz; a spanek a `file` fájlhoz tartoznak.
+pub struct LineIndex { starts: Vec<usize>, file: usize }
+
+impl LineIndex {
+    pub fn new(src: &str, file: usize) -> Self {
+        let mut starts = vec![0];
+        starts.extend(src.match_indices('\n').map(|(i, _)| i + 1));
+        Self { starts, file }
+    }
+
+    pub fn span(&self, src: &str, start: usize, end: usize) -> Span {
+        let line = self.starts.partition_point(|&s| s <= start);
+        let col = src[self.starts[line - 1]..start].chars().count() + 1;
+        Span { start, end, line: line as u32, col: col as u32, file: self.file }
+    }
+}
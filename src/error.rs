@@ -0,0 +1,497 @@
+use std::fmt;
+
+use crate::{diag::snippet, langpack::Langpack, token::Span};
+
+pub type Result<T> = std::result::Result<T, RunaError>;
+
+/// A fordítási lánc és a VM közös hibatípusa. Minden altípusnak stabil kódja van (`code()`),
+/// erre lehet illeszteni szöveg helyett.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunaError {
+    Langpack(LangpackError),
+    Cli(CliError),
+    Lex { kind: LexError, span: Span },
+    Parse { kind: ParseError, span: Span },
+    Resolve { kind: ResolveError, span: Span },
+    Runtime { kind: RuntimeError, span: Option<Span> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LangpackError {
+    InvalidJson(String),
+    UnknownKeywordKind(String),
+    UnknownBuiltin(String),
+    MissingKeyword(String),
+    LocaleMismatch { used: String, detected: String },
+    ConflictingLocale { pragma: String, suffix: String },
+    UnknownLocale(String),
+}
+
+/// A parancssor hibái; forráshely nélküliek.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CliError {
+    MissingValue(String),
+    UnknownArg(String),
+    ReadFile { path: String, reason: String },
+    /// A keresett belépési pontok, `/`-rel elválasztva.
+    NoEntry(String),
+    Usage(String),
+    UnknownSourceLocale(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexError {
+    UnexpectedChar(String),
+    BadStringLiteral,
+    UnknownEscape(char),
+    UnterminatedEscape,
+    BadIntLiteral(String),
+    BadFloatLiteral(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    UnexpectedToken { expected: String, found: String },
+    ExpectedExpr(String),
+    ExpectedIdent(String),
+    TopLevelStmt,
+    InvalidAssignTarget,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResolveError {
+    UnknownIdent(String),
+    AssignUndeclared(String),
+    BreakOutsideLoop,
+    ContinueOutsideLoop,
+    DuplicateFunction(String),
+    DuplicateClass(String),
+    UnknownFunction(String),
+    KeywordCollision(String),
+    SelfOutsideMethod,
+    DuplicateField(String),
+    InheritanceCycle(String),
+    UnknownClass(String),
+    SuperWithoutParent,
+    ModuleNotFound(String),
+    ImportCycle(String),
+    DuplicateImport(String),
+    DuplicateGlobal(String),
+    GlobalBeforeInit(String),
+    /// `decl`: a `let` deklaráció helye, megjegyzésként a hiba mellett.
+    AssignImmutable { name: String, decl: Span },
+    BuiltinCollision(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    StackUnderflow,
+    UnknownFunction(String),
+    ArityMismatch { name: String, expected: usize, got: usize },
+    IndexOutOfRange { index: i64, len: usize },
+    TypeMismatch { op: String, left: String, right: String },
+    BadArgument { func: String, expected: String, got: String },
+    UnaryTypeMismatch { op: String, operand: String },
+    NoSuchMethod { class: String, name: String },
+    NoSuchField { class: String, name: String },
+    NotAnObject { name: String, got: String },
+    UninitializedGlobal(String),
+    NotCallable(String),
+    BadConversion { value: String, target: String },
+    KeyNotFound(String),
+    BadMapKey(String),
+    DivisionByZero,
+    /// A `bigint` feature-rel nem fordul elő: a túlcsordult eredmény BigInt lesz.
+    #[cfg_attr(feature = "bigint", allow(dead_code))]
+    IntegerOverflow { op: String, left: String, right: String },
+    FormatArgs { expected: usize, got: usize },
+    AssignImmutableField { class: String, name: String },
+    NotABool { op: String, got: String },
+    /// A megengedett hívási mélység.
+    CallDepth(usize),
+    /// `for (x in v)`: `v` típusa.
+    NotIterable(String),
+}
+
+impl RunaError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            RunaError::Langpack(k) => match k {
+                LangpackError::InvalidJson(_) => "E0001",
+                LangpackError::UnknownKeywordKind(_) => "E0002",
+                LangpackError::UnknownBuiltin(_) => "E0003",
+                LangpackError::MissingKeyword(_) => "E0004",
+                LangpackError::LocaleMismatch { .. } => "E0005",
+                LangpackError::ConflictingLocale { .. } => "E0006",
+                LangpackError::UnknownLocale(_) => "E0007",
+            },
+            RunaError::Cli(k) => match k {
+                CliError::MissingValue(_) => "E0501",
+                CliError::UnknownArg(_) => "E0502",
+                CliError::ReadFile { .. } => "E0503",
+                CliError::NoEntry(_) => "E0504",
+                CliError::Usage(_) => "E0505",
+                CliError::UnknownSourceLocale(_) => "E0506",
+            },
+            RunaError::Lex { kind, .. } => match kind {
+                LexError::UnexpectedChar(_) => "E0101",
+                LexError::BadStringLiteral => "E0102",
+                LexError::UnknownEscape(_) => "E0103",
+                LexError::UnterminatedEscape => "E0104",
+                LexError::BadIntLiteral(_) => "E0105",
+                LexError::BadFloatLiteral(_) => "E0106",
+            },
+            RunaError::Parse { kind, .. } => match kind {
+                ParseError::UnexpectedToken { .. } => "E0201",
+                ParseError::ExpectedExpr(_) => "E0202",
+                ParseError::ExpectedIdent(_) => "E0203",
+                ParseError::TopLevelStmt => "E0204",
+                ParseError::InvalidAssignTarget => "E0205",
+            },
+            RunaError::Resolve { kind, .. } => match kind {
+                ResolveError::UnknownIdent(_) => "E0301",
+                ResolveError::AssignUndeclared(_) => "E0302",
+                ResolveError::BreakOutsideLoop => "E0303",
+                ResolveError::ContinueOutsideLoop => "E0304",
+                ResolveError::DuplicateFunction(_) => "E0306",
+                ResolveError::DuplicateClass(_) => "E0307",
+                ResolveError::UnknownFunction(_) => "E0308",
+                ResolveError::KeywordCollision(_) => "E0309",
+                ResolveError::SelfOutsideMethod => "E0310",
+                ResolveError::DuplicateField(_) => "E0311",
+                ResolveError::InheritanceCycle(_) => "E0312",
+                ResolveError::UnknownClass(_) => "E0313",
+                ResolveError::SuperWithoutParent => "E0314",
+                ResolveError::ModuleNotFound(_) => "E0315",
+                ResolveError::ImportCycle(_) => "E0316",
+                ResolveError::DuplicateImport(_) => "E0317",
+                ResolveError::DuplicateGlobal(_) => "E0318",
+                ResolveError::GlobalBeforeInit(_) => "E0319",
+                ResolveError::AssignImmutable { .. } => "E0320",
+                ResolveError::BuiltinCollision(_) => "E0321",
+            },
+            RunaError::Runtime { kind, .. } => match kind {
+                RuntimeError::StackUnderflow => "E0401",
+                RuntimeError::UnknownFunction(_) => "E0402",
+                RuntimeError::ArityMismatch { .. } => "E0403",
+                RuntimeError::IndexOutOfRange { .. } => "E0404",
+                RuntimeError::TypeMismatch { .. } => "E0405",
+                RuntimeError::BadArgument { .. } => "E0406",
+                RuntimeError::UnaryTypeMismatch { .. } => "E0407",
+                RuntimeError::NoSuchMethod { .. } => "E0408",
+                RuntimeError::NoSuchField { .. } => "E0409",
+                RuntimeError::NotAnObject { .. } => "E0410",
+                RuntimeError::UninitializedGlobal(_) => "E0411",
+                RuntimeError::NotCallable(_) => "E0412",
+                RuntimeError::BadConversion { .. } => "E0413",
+                RuntimeError::KeyNotFound(_) => "E0414",
+                RuntimeError::BadMapKey(_) => "E0415",
+                RuntimeError::DivisionByZero => "E0416",
+                RuntimeError::IntegerOverflow { .. } => "E0417",
+                RuntimeError::FormatArgs { .. } => "E0418",
+                RuntimeError::AssignImmutableField { .. } => "E0419",
+                RuntimeError::NotABool { .. } => "E0420",
+                RuntimeError::CallDepth(_) => "E0421",
+                RuntimeError::NotIterable(_) => "E0422",
+            },
+        }
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            RunaError::Langpack(_) | RunaError::Cli(_) => None,
+            RunaError::Lex { span, .. } | RunaError::Parse { span, .. } | RunaError::Resolve { span, .. } => Some(*span),
+            RunaError::Runtime { span, .. } => *span,
+        }
+    }
+
+    /// Második hely a hibához (pl. a deklaráció); az üzenete a `<kód>.note` sablon.
+    pub fn note(&self) -> Option<Span> {
+        match self {
+            RunaError::Resolve { kind: ResolveError::AssignImmutable { decl, .. }, .. } => Some(*decl),
+            _ => None,
+        }
+    }
+
+    /// A sablon paraméterei (`{név}` helyére kerülnek).
+    pub fn args(&self) -> Vec<(&'static str, String)> {
+        match self {
+            RunaError::Langpack(k) => match k {
+                LangpackError::InvalidJson(e) => vec![("details", e.clone())],
+                LangpackError::UnknownKeywordKind(k) => vec![("kind", k.clone())],
+                LangpackError::UnknownBuiltin(n) => vec![("name", n.clone())],
+                LangpackError::MissingKeyword(k) => vec![("kind", k.clone())],
+                LangpackError::LocaleMismatch { used, detected } => vec![("used", used.clone()), ("detected", detected.clone())],
+                LangpackError::ConflictingLocale { pragma, suffix } => vec![("pragma", pragma.clone()), ("suffix", suffix.clone())],
+                LangpackError::UnknownLocale(l) => vec![("locale", l.clone())],
+            },
+            RunaError::Cli(k) => match k {
+                CliError::MissingValue(flag) => vec![("flag", flag.clone())],
+                CliError::UnknownArg(arg) => vec![("arg", arg.clone())],
+                CliError::ReadFile { path, reason } => vec![("path", path.clone()), ("reason", reason.clone())],
+                CliError::NoEntry(expected) => vec![("expected", expected.clone())],
+                CliError::Usage(usage) => vec![("usage", usage.clone())],
+                CliError::UnknownSourceLocale(path) => vec![("path", path.clone())],
+            },
+            RunaError::Lex { kind, .. } => match kind {
+                LexError::UnexpectedChar(c) => vec![("char", c.clone())],
+                LexError::BadStringLiteral | LexError::UnterminatedEscape => vec![],
+                LexError::UnknownEscape(c) => vec![("char", c.to_string())],
+                LexError::BadIntLiteral(e) | LexError::BadFloatLiteral(e) => vec![("details", e.clone())],
+            },
+            RunaError::Parse { kind, .. } => match kind {
+                ParseError::UnexpectedToken { expected, found } => vec![("expected", expected.clone()), ("found", found.clone())],
+                ParseError::ExpectedExpr(found) | ParseError::ExpectedIdent(found) => vec![("found", found.clone())],
+                ParseError::TopLevelStmt | ParseError::InvalidAssignTarget => vec![],
+            },
+            RunaError::Resolve { kind, .. } => match kind {
+                ResolveError::UnknownIdent(n) | ResolveError::AssignUndeclared(n)
+                | ResolveError::DuplicateFunction(n) | ResolveError::DuplicateClass(n)
+                | ResolveError::UnknownFunction(n) | ResolveError::KeywordCollision(n)
+                | ResolveError::DuplicateField(n) | ResolveError::InheritanceCycle(n)
+                | ResolveError::UnknownClass(n) | ResolveError::ModuleNotFound(n) | ResolveError::ImportCycle(n)
+                | ResolveError::DuplicateImport(n) | ResolveError::DuplicateGlobal(n)
+                | ResolveError::GlobalBeforeInit(n) | ResolveError::AssignImmutable { name: n, .. }
+                | ResolveError::BuiltinCollision(n) => vec![("name", n.clone())],
+                ResolveError::BreakOutsideLoop | ResolveError::ContinueOutsideLoop
+                | ResolveError::SelfOutsideMethod | ResolveError::SuperWithoutParent => vec![],
+            },
+            RunaError::Runtime { kind, .. } => match kind {
+                RuntimeError::StackUnderflow | RuntimeError::DivisionByZero => vec![],
+                RuntimeError::UnknownFunction(n) | RuntimeError::UninitializedGlobal(n) => vec![("name", n.clone())],
+                RuntimeError::NotCallable(got) | RuntimeError::NotIterable(got) => vec![("got", got.clone())],
+                RuntimeError::BadConversion { value, target } => vec![("value", value.clone()), ("target", target.clone())],
+                RuntimeError::KeyNotFound(key) => vec![("key", key.clone())],
+                RuntimeError::BadMapKey(got) => vec![("got", got.clone())],
+                RuntimeError::ArityMismatch { name, expected, got } => vec![("name", name.clone()), ("expected", expected.to_string()), ("got", got.to_string())],
+                RuntimeError::FormatArgs { expected, got } => vec![("expected", expected.to_string()), ("got", got.to_string())],
+                RuntimeError::IndexOutOfRange { index, len } => vec![("index", index.to_string()), ("len", len.to_string())],
+                RuntimeError::TypeMismatch { op, left, right } | RuntimeError::IntegerOverflow { op, left, right } => vec![("op", op.clone()), ("left", left.clone()), ("right", right.clone())],
+                RuntimeError::BadArgument { func, expected, got } => vec![("func", func.clone()), ("expected", expected.clone()), ("got", got.clone())],
+                RuntimeError::UnaryTypeMismatch { op, operand } => vec![("op", op.clone()), ("operand", operand.clone())],
+                RuntimeError::NotABool { op, got } => vec![("op", op.clone()), ("got", got.clone())],
+                RuntimeError::CallDepth(limit) => vec![("limit", limit.to_string())],
+                RuntimeError::NoSuchMethod { class, name } | RuntimeError::NoSuchField { class, name }
+                | RuntimeError::AssignImmutableField { class, name } => vec![("class", class.clone()), ("name", name.clone())],
+                RuntimeError::NotAnObject { name, got } => vec![("name", name.clone()), ("got", got.clone())],
+            },
+        }
+    }
+
+    /// Üzenet a langpack sablonjával; ha a kód hiányzik belőle, az angol alapértelmezéssel.
+    pub fn message(&self, lp: &Langpack) -> String { self.template(lp, self.code()) }
+
+    fn template(&self, lp: &Langpack, key: &str) -> String {
+        let tpl = lp.message(key).unwrap_or_else(|| default_message(key));
+        fill(tpl, &self.args())
+    }
+
+    /// fájl:sor:oszlop: error[kód]: üzenet, alatta a forrássor jelöléssel; ha a megjegyzés
+    /// ugyanebben a fájlban van, utána az is (más fájlban lévőt a `render_note` ad).
+    pub fn render(&self, file: &str, src: &str, lp: &Langpack) -> String {
+        let label = lp.message("error").unwrap_or("error");
+        match self.span() {
+            Some(span) => {
+                let mut out = format!("{}:{}:{}: {}[{}]: {}\n", file, span.line, span.col, label, self.code(), self.message(lp));
+                out.push_str(&snippet(src, span));
+                if self.note().is_some_and(|n| n.file == span.file) { out.push_str(&self.render_note(file, src, lp)); }
+                out
+            }
+            None => format!("{}: {}[{}]: {}\n", file, label, self.code(), self.message(lp)),
+        }
+    }
+
+    /// fájl:sor:oszlop: note: üzenet, a megjegyzés helyével; üres, ha a hibához nincs megjegyzés.
+    pub fn render_note(&self, file: &str, src: &str, lp: &Langpack) -> String {
+        let Some(span) = self.note() else { return String::new() };
+        let label = lp.message("note").unwrap_or("note");
+        format!("{}:{}:{}: {}: {}\n{}", file, span.line, span.col, label, self.template(lp, &format!("{}.note", self.code())), snippet(src, span))
+    }
+}
+
+/// Angol alapértelmezett üzenetsablonok, kódonként. A langpack `messages` szekciója ezeket írja felül.
+pub const DEFAULT_MESSAGES: &[(&str, &str)] = &[
+    ("E0001", "invalid langpack JSON: {details}"),
+    ("E0002", "unknown keyword kind: {kind}"),
+    ("E0003", "unknown built-in function: {name}"),
+    ("E0004", "the langpack has no word for keyword {kind}"),
+    ("E0005", "the file does not compile as locale '{used}' but does as '{detected}'; pass --locale={detected} or start the file with `// runa: locale={detected}`"),
+    ("E0006", "the header declares locale '{pragma}' but the file name says '{suffix}'"),
+    ("E0007", "no langpack found for locale '{locale}' (see `runa langpack list`)"),
+    ("E0101", "unexpected character '{char}'"),
+    ("E0102", "malformed string literal"),
+    ("E0103", "unknown escape sequence: \\{char}"),
+    ("E0104", "unterminated escape sequence"),
+    ("E0105", "invalid integer literal: {details}"),
+    ("E0106", "invalid float literal: {details}"),
+    ("E0201", "expected {expected}, found {found}"),
+    ("E0202", "expected an expression, found {found}"),
+    ("E0203", "expected an identifier, found {found}"),
+    ("E0204", "only class, fn, let and import are allowed at the top level"),
+    ("E0205", "invalid assignment target; expected a variable, an indexed element or a field"),
+    ("E0301", "unknown identifier: {name}"),
+    ("E0302", "assignment to undeclared variable: {name}"),
+    ("E0303", "break outside of a loop"),
+    ("E0304", "continue outside of a loop"),
+    ("E0306", "function already defined: {name}"),
+    ("E0307", "class already defined: {name}"),
+    ("E0308", "unknown function: {name}"),
+    ("E0309", "`{name}` is a keyword in the target locale; rename it before translating"),
+    ("E0310", "`self` can only be used inside a method"),
+    ("E0311", "field already defined: {name}"),
+    ("E0312", "inheritance cycle through class {name}"),
+    ("E0313", "unknown class: {name}"),
+    ("E0314", "`super` can only be used in a method of a class with a parent class"),
+    ("E0315", "cannot find module {name}"),
+    ("E0316", "import cycle: {name}"),
+    ("E0317", "two different modules are imported as {name}"),
+    ("E0318", "global variable already defined: {name}"),
+    ("E0319", "global variable {name} is used before its initializer runs"),
+    ("E0320", "cannot assign to {name}: it is not declared with `var`"),
+    ("E0320.note", "{name} is declared here; declare it with `var` to allow assignment"),
+    ("E0321", "`{name}` is a built-in function in the target locale but is declared in this file; rename it before translating"),
+    ("E0401", "stack underflow"),
+    ("E0402", "unknown function: {name}"),
+    ("E0403", "{name} expects {expected} argument(s), got {got}"),
+    ("E0404", "index out of range: {index} (length {len})"),
+    ("E0405", "unsupported operand types: {left} {op} {right}"),
+    ("E0406", "{func}: bad argument type, expected {expected}, got {got}"),
+    ("E0407", "unsupported operand type: {op}{operand}"),
+    ("E0408", "{class} has no method {name}"),
+    ("E0409", "{class} has no field {name}"),
+    ("E0410", "`.{name}` needs an object, got {got}"),
+    ("E0411", "global variable {name} was read before it was initialized"),
+    ("E0412", "a value of type {got} cannot be called"),
+    ("E0413", "cannot convert {value} to {target}"),
+    ("E0414", "key not found: {key}"),
+    ("E0415", "a value of type {got} cannot be a map key (Int or Str expected)"),
+    ("E0416", "division by zero"),
+    ("E0417", "integer overflow: {left} {op} {right}"),
+    ("E0418", "format: the format string has {expected} placeholders, got {got} values"),
+    ("E0419", "cannot assign to field {name} of {class}: it is not declared with `var`"),
+    ("E0420", "`{op}` needs Bool operands, got {got}"),
+    ("E0421", "call depth limit exceeded: more than {limit} nested calls"),
+    ("E0422", "a value of type {got} cannot be iterated (Array, Map or Str expected)"),
+    ("E0501", "{flag} needs a value"),
+    ("E0502", "unknown argument: {arg}"),
+    ("E0503", "cannot read {path}: {reason}"),
+    ("E0504", "no entry function found (expected: {expected})"),
+    ("E0505", "usage: {usage}"),
+    ("E0506", "cannot detect the source locale of {path}; pass --from=<locale>"),
+];
+
+fn default_message(code: &str) -> &'static str {
+    DEFAULT_MESSAGES.iter().find(|(c, _)| *c == code).map(|(_, m)| *m).unwrap_or("")
+}
+
+fn fill(tpl: &str, args: &[(&'static str, String)]) -> String {
+    args.iter().fold(tpl.to_string(), |acc, (k, v)| acc.replace(&format!("{{{}}}", k), v))
+}
+
+impl fmt::Display for RunaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = fill(default_message(self.code()), &self.args());
+        match self.span() {
+            Some(span) => write!(f, "{}:{}: error[{}]: {}", span.line, span.col, self.code(), msg),
+            None => write!(f, "error[{}]: {}", self.code(), msg),
+        }
+    }
+}
+
+impl std::error::Error for RunaError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{langpack::SearchPath, loader::Loader, vm::VM};
+
+    /// Egy `main` függvényes en program hibája (fordítási vagy futásidejű).
+    fn error_of(src: &str) -> RunaError {
+        let search = SearchPath::new(Vec::new());
+        let ir = match Loader::new(&search).compile("test.rn", src.to_string(), "en") {
+            Ok(ir) => ir,
+            Err(e) => return e,
+        };
+        VM::new(ir).run("main").expect_err("program should fail")
+    }
+
+    #[test]
+    fn runtime_kinds() {
+        let e = error_of("fn main() { let xs = [1, 2]; return xs[5]; }");
+        assert!(matches!(e, RunaError::Runtime { kind: RuntimeError::IndexOutOfRange { index: 5, len: 2 }, span: Some(_) }), "{:?}", e);
+        assert_eq!(e.code(), "E0404");
+
+        let e = error_of("fn main() { return true - 1; }");
+        assert!(matches!(&e, RunaError::Runtime { kind: RuntimeError::TypeMismatch { op, .. }, .. } if op == "-"), "{:?}", e);
+        assert_eq!(e.code(), "E0405");
+
+        let e = error_of(r#"fn main() { let m = {"a": 1}; return m["b"]; }"#);
+        assert!(matches!(e, RunaError::Runtime { kind: RuntimeError::KeyNotFound(_), .. }), "{:?}", e);
+        assert_eq!(e.code(), "E0414");
+
+        let e = error_of("fn main() { return 1 / 0; }");
+        assert!(matches!(e, RunaError::Runtime { kind: RuntimeError::DivisionByZero, .. }), "{:?}", e);
+        assert_eq!(e.code(), "E0416");
+
+        let e = error_of("class P { let id = 1; }\nfn main() { let p = P(); p.id += 1; }");
+        assert!(matches!(&e, RunaError::Runtime { kind: RuntimeError::AssignImmutableField { class, name }, .. } if class == "P" && name == "id"), "{:?}", e);
+        assert_eq!(e.code(), "E0419");
+    }
+
+    #[test]
+    fn compile_kinds() {
+        let e = error_of("fn main() { return 1 @ 2; }");
+        assert!(matches!(e, RunaError::Lex { kind: LexError::UnexpectedChar(_), .. }), "{:?}", e);
+        assert_eq!(e.code(), "E0101");
+
+        let e = error_of("fn main() { let x = ; }");
+        assert!(matches!(e, RunaError::Parse { kind: ParseError::ExpectedExpr(_), .. }), "{:?}", e);
+        assert_eq!(e.code(), "E0202");
+
+        let e = error_of("fn main() { return nope; }");
+        assert!(matches!(&e, RunaError::Resolve { kind: ResolveError::UnknownIdent(n), .. } if n == "nope"), "{:?}", e);
+        assert_eq!(e.code(), "E0301");
+
+        let e = error_of("fn main() { let x = 1; x = 2; }");
+        assert!(matches!(e, RunaError::Resolve { kind: ResolveError::AssignImmutable { .. }, .. }), "{:?}", e);
+        assert_eq!(e.code(), "E0320");
+
+        // `let` mezőt csak a saját konstruktor állíthat be, más metódus, leszármazott és névtelen függvény nem
+        for src in [
+            "class P { let id; fn P(v) { self.id = v; } fn set(v) { self.id = v; } }\nfn main() { return P(2); }",
+            "class P { let id; }\nclass Q : P { fn Q(v) { self.id = v; } }\nfn main() { return Q(2); }",
+            "class P { let id; fn P(v) { let f = fn() { self.id = v; }; f(); } }\nfn main() { return P(2); }",
+        ] {
+            let e = error_of(src);
+            assert!(matches!(&e, RunaError::Resolve { kind: ResolveError::AssignImmutable { name, .. }, .. } if name == "id"), "{}: {:?}", src, e);
+        }
+    }
+
+    /// A kódok stabilak: a langpackek és a felhasználók ezekre hivatkoznak.
+    #[test]
+    fn codes_are_stable() {
+        let span = Span::default();
+        let cases = [
+            (RunaError::Langpack(LangpackError::UnknownLocale("xx".into())), "E0007"),
+            (RunaError::Lex { kind: LexError::BadFloatLiteral("1e".into()), span }, "E0106"),
+            (RunaError::Parse { kind: ParseError::InvalidAssignTarget, span }, "E0205"),
+            (RunaError::Resolve { kind: ResolveError::KeywordCollision("ha".into()), span }, "E0309"),
+            (RunaError::Resolve { kind: ResolveError::BuiltinCollision("hossz".into()), span }, "E0321"),
+            (RunaError::Runtime { kind: RuntimeError::StackUnderflow, span: None }, "E0401"),
+            (RunaError::Runtime { kind: RuntimeError::BadMapKey("Array".into()), span: None }, "E0415"),
+            (RunaError::Runtime { kind: RuntimeError::FormatArgs { expected: 1, got: 2 }, span: None }, "E0418"),
+            (RunaError::Runtime { kind: RuntimeError::NotIterable("Int".into()), span: None }, "E0422"),
+            (RunaError::Cli(CliError::NoEntry("main/fo".into())), "E0504"),
+        ];
+        for (e, code) in cases { assert_eq!(e.code(), code, "{:?}", e); }
+    }
+
+    #[test]
+    fn every_code_has_one_default_message() {
+        let mut codes: Vec<&str> = DEFAULT_MESSAGES.iter().map(|(c, _)| *c).collect();
+        let n = codes.len();
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(codes.len(), n);
+    }
+}
@@ -0,0 +1,114 @@
+use std::{cell::RefCell, collections::{HashMap, HashSet}, rc::{Rc, Weak}};
+
+use crate::vm::{Object, OrderedMap, Value};
+
+/// Egy nyilvántartott heap-objektum. A gyenge referencia nem tartja életben az objektumot, de a
+/// címét igen, így a cím amíg a bejegyzés él, nem kerülhet másik objektumhoz.
+enum HeapRef {
+    Array(Weak<RefCell<Vec<Value>>>),
+    Map(Weak<RefCell<OrderedMap>>),
+    Object(Weak<RefCell<Object>>),
+    /// Closure által befogott változó cellája.
+    Cell(Weak<RefCell<Value>>),
+}
+
+/// A gyűjtő számlálói; a `gc_stats()` ezeket adja vissza.
+#[derive(Clone, Copy, Default)]
+pub struct GcStats { pub collections: usize, pub allocated: usize, pub freed: usize, pub live: usize }
+
+/// Mark-and-sweep gyűjtő a referenciaszámlált értékek fölött. A körök nélküli szemetet az `Rc`
+/// azonnal felszabadítja; a gyűjtő a gyökerekből el nem érhető tömbök, map-ek, objektumok és
+/// cellák tartalmát üríti ki, ezzel felbontja a köröket, és a maradékot az `Rc` engedi el.
+pub struct Heap {
+    objects: HashMap<usize, HeapRef>,
+    /// Ennyi új objektum után fut a következő gyűjtés (`stress`: minden foglalás után).
+    threshold: usize,
+    since: usize,
+    pub stress: bool,
+    pub stats: GcStats,
+}
+
+const MIN_THRESHOLD: usize = 1024;
+
+impl Heap {
+    pub fn new() -> Self {
+        Self { objects: HashMap::new(), threshold: MIN_THRESHOLD, since: 0, stress: false, stats: GcStats::default() }
+    }
+
+    /// Egy érték nyilvántartásba vétele; a már ismert objektum és a skalár nem számít.
+    pub fn track(&mut self, v: &Value) {
+        let (addr, r) = match v {
+            Value::Array(a) => (Rc::as_ptr(a) as usize, HeapRef::Array(Rc::downgrade(a))),
+            Value::Map(m) => (Rc::as_ptr(m) as usize, HeapRef::Map(Rc::downgrade(m))),
+            Value::Object(o) => (Rc::as_ptr(o) as usize, HeapRef::Object(Rc::downgrade(o))),
+            _ => return,
+        };
+        self.insert(addr, r);
+    }
+
+    pub fn track_cell(&mut self, c: &Rc<RefCell<Value>>) { self.insert(Rc::as_ptr(c) as usize, HeapRef::Cell(Rc::downgrade(c))); }
+
+    fn insert(&mut self, addr: usize, r: HeapRef) {
+        if self.objects.contains_key(&addr) { return; }
+        self.objects.insert(addr, r);
+        self.since += 1;
+        self.stats.allocated += 1;
+    }
+
+    pub fn should_collect(&self) -> bool { self.since > 0 && (self.stress || self.since >= self.threshold) }
+
+    /// Sweep: a `marker` által el nem ért élő objektumok kiürítése. A kiürített tartalom csak a
+    /// kölcsönzések elengedése után esik szét, mert a felszabadulás további objektumokat érinthet.
+    pub fn sweep(&mut self, marker: Marker) {
+        let mut garbage: Vec<Value> = Vec::new();
+        let mut freed = 0;
+        self.objects.retain(|addr, r| {
+            if marker.seen.contains(addr) { return true; }
+            match r {
+                HeapRef::Array(w) => if let Some(a) = w.upgrade() { garbage.append(&mut a.borrow_mut()); freed += 1; },
+                HeapRef::Map(w) => if let Some(m) = w.upgrade() { garbage.push(Value::map(std::mem::take(&mut *m.borrow_mut()))); freed += 1; },
+                HeapRef::Object(w) => if let Some(o) = w.upgrade() {
+                    garbage.extend(o.borrow_mut().fields.iter_mut().map(|f| std::mem::replace(f, Value::Void)));
+                    freed += 1;
+                },
+                HeapRef::Cell(w) => if let Some(c) = w.upgrade() { garbage.push(c.replace(Value::Void)); freed += 1; },
+            }
+            false
+        });
+        drop(garbage);
+        self.stats.collections += 1;
+        self.stats.freed += freed;
+        self.stats.live = self.objects.len();
+        self.since = 0;
+        self.threshold = MIN_THRESHOLD.max(2 * self.objects.len());
+    }
+}
+
+/// Mark: a gyökerekből elérhető objektumok címei; munkalistával, hogy a mély szerkezet se
+/// fogyassza el a hívási vermet.
+#[derive(Default)]
+pub struct Marker { seen: HashSet<usize>, work: Vec<Value> }
+
+impl Marker {
+    pub fn value(&mut self, v: &Value) { self.work.push(v.clone()); self.drain(); }
+
+    pub fn cell(&mut self, c: &Rc<RefCell<Value>>) { self.visit_cell(c); self.drain(); }
+
+    fn drain(&mut self) {
+        while let Some(v) = self.work.pop() {
+            match &v {
+                Value::Array(a) => if self.seen.insert(Rc::as_ptr(a) as usize) { self.work.extend(a.borrow().iter().cloned()); },
+                Value::Map(m) => if self.seen.insert(Rc::as_ptr(m) as usize) { self.work.extend(m.borrow().iter().map(|(_, v)| v.clone())); },
+                Value::Object(o) => if self.seen.insert(Rc::as_ptr(o) as usize) { self.work.extend(o.borrow().fields.iter().cloned()); },
+                Value::Func(c) => for cell in &c.upvalues { self.visit_cell(cell); },
+                Value::Int(_) | Value::Float(_) | Value::Str(_) | Value::Bool(_) | Value::Builtin(_) | Value::Void => {}
+                #[cfg(feature = "bigint")]
+                Value::BigInt(_) => {}
+            }
+        }
+    }
+
+    fn visit_cell(&mut self, c: &Rc<RefCell<Value>>) {
+        if self.seen.insert(Rc::as_ptr(c) as usize) { self.work.push(c.borrow().clone()); }
+    }
+}
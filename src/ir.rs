@@ -1,33 +1,134 @@
+use std::collections::HashMap;
+
+use crate::token::Span;
+
 #[derive(Debug, Clone)]
 pub enum Op {
     PushInt(i64),
+    PushFloat(f64),
     PushStr(String),
     PushBool(bool),
     PushVoid,
     LoadLocal(usize),
+    /// értékadás: ha a slotot closure fogta be, a közös cellába ír
     StoreLocal(usize),
-    Add, Sub, Mul, Div,
+    /// új kötés (deklaráció): a slot korábbi, esetleg befogott cellájától független
+    InitLocal(usize),
+    LoadUpvalue(usize),
+    StoreUpvalue(usize),
+    LoadGlobal(usize),
+    StoreGlobal(usize),
+    Add, Sub, Mul, Div, Mod,
     Eq, Ne, Lt, Le, Gt, Ge,
+    Neg, Not,
     MakeArray(usize),
+    /// [k1, v1, .., kN, vN] -> Map, a felsorolás sorrendjében
+    MakeMap(usize),
     IndexGet,
+    /// [tömb/string, kezdet, vég] -> új tömb/string; a Void határ az eleje, ill. a vége
+    Slice,
+    /// [tömb/map, index, érték] -> (), helyben módosít
+    IndexSet,
+    /// for-in: Map helyett a kulcsainak tömbje, Array és Str változatlan, más érték hiba
+    Iter,
+    /// a verem felső két elemének megkettőzése
+    Dup2,
     CallName(String, usize),
+    /// [függvényérték, arg1..argN] -> eredmény
+    CallValue(usize),
+    /// függvényérték a megnevezett függvényből, a felsorolt befogott változókkal
+    MakeClosure(String, Vec<Capture>),
+    CallBuiltin(Builtin, usize),
+    /// beépített függvény mint érték
+    PushBuiltin(Builtin),
+    /// [fogadó, arg1..argN] -> a fogadó osztályának metódustáblájából hívott metódus eredménye
+    CallMethod(String, usize),
+    /// mint a CallMethod, de a megadott (szülő)osztály táblájából: `super.method(...)`
+    CallSuper(usize, String, usize),
+    /// új példány: mezők kezdőértéke, majd a konstruktor (az osztállyal azonos nevű metódus) az N argumentummal
+    New(usize, usize),
+    GetField(String),
+    /// [objektum, érték] -> (), helyben módosít; `let` mezőre hiba
+    SetField(String),
+    /// mint a SetField, a `let` mezőkre is: a mezők kezdőértékadása és a konstruktor
+    InitField(String),
+    Dup,
     Pop,
     Jump(usize),
+    /// hibakezelő a megadott címmel; a hiba map-je a kezelő indulásakor a verem tetején
+    TryBegin(usize),
+    TryEnd,
     JumpIfFalse(usize),
+    /// [x] -> [x]; hiba, ha x nem Bool (a `&&` / `||` operandusai)
+    CheckBool(&'static str),
     Return,
 }
 
+/// Egy befogott változó forrása a closure létrehozásakor: a létrehozó függvény lokális slotja,
+/// vagy a létrehozó (maga is closure) függvény saját befogott változója.
+#[derive(Debug, Clone, Copy)]
+pub enum Capture { Local(usize), Upvalue(usize) }
+
+/// A VM beépített függvényei, kanonikus (angol) nevükkel. A langpack `builtins` szekciója
+/// lokalizált neveket képez ezekre; a feloldás a kódgenerálásnál történik.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Builtin {
+    Print, Len, Push, Map, Filter, Int, Float, Keys, Values, Has, Remove, GcStats,
+    Split, Join, Trim, Upper, Lower, Contains, Replace, StartsWith, Find, Format,
+}
+
+impl Builtin {
+    pub const ALL: &'static [(&'static str, Builtin)] = &[
+        ("print", Builtin::Print), ("len", Builtin::Len), ("push", Builtin::Push), ("map", Builtin::Map), ("filter", Builtin::Filter),
+        ("int", Builtin::Int), ("float", Builtin::Float),
+        ("keys", Builtin::Keys), ("values", Builtin::Values), ("has", Builtin::Has), ("remove", Builtin::Remove),
+        ("gc_stats", Builtin::GcStats),
+        ("split", Builtin::Split), ("join", Builtin::Join), ("trim", Builtin::Trim), ("upper", Builtin::Upper), ("lower", Builtin::Lower),
+        ("contains", Builtin::Contains), ("replace", Builtin::Replace), ("starts_with", Builtin::StartsWith), ("find", Builtin::Find),
+        ("format", Builtin::Format),
+    ];
+
+    pub fn from_name(name: &str) -> Option<Builtin> { Self::ALL.iter().find(|(n, _)| *n == name).map(|(_, b)| *b) }
+    pub fn name(self) -> &'static str { Self::ALL.iter().find(|(_, b)| *b == self).map(|(n, _)| *n).unwrap_or("?") }
+}
+
+/// Bájtkód, minden utasításhoz a forrásbeli span-nel (futásidejű hibák helyéhez).
 #[derive(Debug, Clone)]
-pub struct Chunk { pub code: Vec<Op> }
-impl Chunk { pub fn new() -> Self { Self { code: Vec::new() } } }
+pub struct Chunk { pub code: Vec<Op>, pub spans: Vec<Span> }
+impl Chunk {
+    pub fn new() -> Self { Self { code: Vec::new(), spans: Vec::new() } }
+    pub fn push(&mut self, op: Op, span: Span) { self.code.push(op); self.spans.push(span); }
+}
 
 pub struct FunctionIR {
     pub name: String,
+    /// A rejtett `self` paraméterrel együtt.
     pub arity: usize,
+    /// Metódus: a 0. lokális a fogadó objektum.
+    pub method: bool,
     pub local_count: usize,
     pub chunk: Chunk,
 }
 
+/// Egy osztály futásidejű leírása, az ősöktől örökölt tagokkal együtt. A mezők sorrendje az
+/// objektum mezővektoráé (előbb az ősöké); `methods` a metódusnévből a függvényindexre képez,
+/// a felülírt metódusoknál a leszármazottéra; `init` a mezők kezdőértékét állítja be.
+#[derive(Debug)]
+pub struct ClassIR {
+    pub name: String,
+    pub fields: Vec<String>,
+    /// `fields`-szel párhuzamosan: `var`-e a mező.
+    pub mutable: Vec<bool>,
+    pub methods: HashMap<String, usize>,
+    pub init: usize,
+    pub ctor: Option<usize>,
+}
+
+/// `globals`: a globális változók teljes (névtérrel ellátott) nevei, indexük szerint; `init`: a
+/// kezdőértéküket beállító függvény, a belépési pont előtt fut.
 pub struct ProgramIR {
     pub functions: Vec<FunctionIR>,
+    pub classes: Vec<ClassIR>,
+    pub globals: Vec<String>,
+    pub init: usize,
 }
@@ -0,0 +1,244 @@
+use std::{collections::HashMap, env, fmt, fs, path::PathBuf};
+
+use serde::{de, Deserialize, Deserializer};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{
+    error::{LangpackError, Result, RunaError, DEFAULT_MESSAGES},
+    ir::Builtin,
+    token::KEYWORDS,
+};
+
+/// Egy `langpacks/*.json` tartalma: kulcsszavak (szó -> TokenKind név), lokalizált
+/// beépített függvénynevek (név -> kanonikus név) és hibakódonként lokalizált üzenetsablonok.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Langpack {
+    /// Fájlbeli sorrendben, az ismétlődő kulcsokat is megtartva (a `check` ezeket is jelzi).
+    #[serde(deserialize_with = "entries")]
+    pub keywords: Vec<(String, String)>,
+    #[serde(default, deserialize_with = "entries")]
+    pub builtins: Vec<(String, String)>,
+    #[serde(default)]
+    pub messages: HashMap<String, String>,
+}
+
+/// A binárisba fordított csomagok; a keresési út könyvtárai ezeket felülírhatják.
+const BUNDLED: &[(&str, &str)] = &[
+    ("en", include_str!("../langpacks/en.json")),
+    ("hu", include_str!("../langpacks/hu.json")),
+];
+
+/// Honnan jött egy csomag (a `langpack list` ezt mutatja).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    File(PathBuf),
+    Bundled,
+}
+
+/// Langpack-keresés sorrendben: `--langpack-dir`, `RUNA_LANGPACK_PATH`, a felhasználói
+/// konfigurációs könyvtár (`<config>/runa/langpacks`), végül a beépített csomagok.
+pub struct SearchPath { dirs: Vec<PathBuf> }
+
+impl SearchPath {
+    pub fn new(cli_dirs: Vec<PathBuf>) -> Self {
+        let mut dirs = cli_dirs;
+        if let Some(paths) = env::var_os("RUNA_LANGPACK_PATH") { dirs.extend(env::split_paths(&paths)); }
+        if let Some(config) = dirs::config_dir() { dirs.push(config.join("runa").join("langpacks")); }
+        Self { dirs }
+    }
+
+    /// Az első találat a lokálé nevére (`<dir>/<locale>.json`).
+    pub fn load(&self, locale: &str) -> Result<Langpack> {
+        for dir in &self.dirs {
+            let path = dir.join(format!("{}.json", locale));
+            if let Ok(json) = fs::read_to_string(&path) {
+                return Langpack::from_json(&json).map_err(|e| match e {
+                    RunaError::Langpack(LangpackError::InvalidJson(d)) => RunaError::Langpack(LangpackError::InvalidJson(format!("{}: {}", path.display(), d))),
+                    e => e,
+                });
+            }
+        }
+        match BUNDLED.iter().find(|(l, _)| *l == locale) {
+            Some((_, json)) => Langpack::from_json(json),
+            None => Err(RunaError::Langpack(LangpackError::UnknownLocale(locale.to_string()))),
+        }
+    }
+
+    /// Van-e csomag a lokáléhoz (betöltés nélkül).
+    pub fn has(&self, locale: &str) -> bool {
+        self.dirs.iter().any(|dir| dir.join(format!("{}.json", locale)).is_file()) || BUNDLED.iter().any(|(l, _)| *l == locale)
+    }
+
+    /// Az elérhető lokálék név szerint rendezve, mindegyik a ténylegesen betöltendő forrással.
+    pub fn list(&self) -> Vec<(String, Source)> {
+        let mut found: Vec<(String, Source)> = Vec::new();
+        for dir in &self.dirs {
+            let Ok(entries) = fs::read_dir(dir) else { continue };
+            let mut files: Vec<PathBuf> = entries.filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|x| x == "json")).collect();
+            files.sort();
+            for path in files {
+                let Some(locale) = path.file_stem().and_then(|s| s.to_str()) else { continue };
+                if !found.iter().any(|(l, _)| l == locale) { found.push((locale.to_string(), Source::File(path.clone()))); }
+            }
+        }
+        for (locale, _) in BUNDLED {
+            if !found.iter().any(|(l, _)| l == locale) { found.push((locale.to_string(), Source::Bundled)); }
+        }
+        found.sort_by(|a, b| a.0.cmp(&b.0));
+        found
+    }
+}
+
+/// A `langpack check` által jelzett problémák.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Issue {
+    MissingKeyword(String),
+    UnknownKind { word: String, kind: String },
+    DuplicateWord(String),
+    DuplicateKind { kind: String, words: Vec<String> },
+    BuiltinCollision(String),
+    UnknownBuiltin { word: String, name: String },
+    InvalidWord(String),
+    UnknownMessage(String),
+}
+
+impl Langpack {
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).map_err(|e| RunaError::Langpack(LangpackError::InvalidJson(e.to_string())))
+    }
+
+    /// A kódhoz tartozó sablon, ha a csomag tartalmazza.
+    pub fn message(&self, code: &str) -> Option<&str> { self.messages.get(code).map(String::as_str) }
+
+    /// A forrásban hívható beépített nevek: a kanonikus nevek mindig, mellettük a csomag lokalizált nevei.
+    pub fn builtin_map(&self) -> Result<HashMap<String, Builtin>> {
+        let mut map: HashMap<String, Builtin> = Builtin::ALL.iter().map(|(n, b)| (n.to_string(), *b)).collect();
+        for (word, name) in &self.builtins {
+            let b = Builtin::from_name(name).ok_or_else(|| RunaError::Langpack(LangpackError::UnknownBuiltin(name.clone())))?;
+            map.insert(word.nfc().collect(), b);
+        }
+        Ok(map)
+    }
+
+    /// Teljességi és konzisztencia-ellenőrzés a teljes kulcsszókészlet ellen.
+    pub fn check(&self) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        let mut by_kind: Vec<(&str, Vec<String>)> = KEYWORDS.iter().map(|(k, _)| (*k, Vec::new())).collect();
+        let builtin_words: Vec<String> = Builtin::ALL.iter().map(|(n, _)| n.to_string())
+            .chain(self.builtins.iter().map(|(w, _)| w.nfc().collect())).collect();
+
+        for (word, kind) in &self.keywords {
+            let norm: String = word.split_whitespace().map(|w| w.nfc().collect::<String>()).collect::<Vec<_>>().join(" ");
+            *seen.entry(norm.clone()).or_default() += 1;
+            if seen[&norm] == 2 { issues.push(Issue::DuplicateWord(norm.clone())); }
+            if norm.is_empty() || !norm.split(' ').all(is_ident) { issues.push(Issue::InvalidWord(word.clone())); }
+            if norm.split(' ').any(|w| builtin_words.iter().any(|b| b == w)) { issues.push(Issue::BuiltinCollision(norm.clone())); }
+            match by_kind.iter_mut().find(|(k, _)| k == kind) {
+                Some((_, words)) => { if !words.contains(&norm) { words.push(norm); } }
+                None => issues.push(Issue::UnknownKind { word: word.clone(), kind: kind.clone() }),
+            }
+        }
+        for (word, name) in &self.builtins {
+            if !is_ident(&word.nfc().collect::<String>()) { issues.push(Issue::InvalidWord(word.clone())); }
+            if Builtin::from_name(name).is_none() { issues.push(Issue::UnknownBuiltin { word: word.clone(), name: name.clone() }); }
+        }
+        for (kind, words) in by_kind {
+            match words.len() {
+                0 => issues.push(Issue::MissingKeyword(kind.to_string())),
+                1 => {}
+                _ => issues.push(Issue::DuplicateKind { kind: kind.to_string(), words }),
+            }
+        }
+        let mut unknown: Vec<&String> = self.messages.keys()
+            .filter(|k| *k != "error" && *k != "note" && !DEFAULT_MESSAGES.iter().any(|(c, _)| c == k)).collect();
+        unknown.sort();
+        issues.extend(unknown.into_iter().map(|k| Issue::UnknownMessage(k.clone())));
+        issues
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::MissingKeyword(k) => write!(f, "missing keyword: {}", k),
+            Issue::UnknownKind { word, kind } => write!(f, "unknown keyword kind {:?} for {:?}", kind, word),
+            Issue::DuplicateWord(w) => write!(f, "word defined more than once: {:?}", w),
+            Issue::DuplicateKind { kind, words } => write!(f, "{} is mapped by several words: {:?}", kind, words),
+            Issue::BuiltinCollision(w) => write!(f, "{:?} collides with a built-in function name", w),
+            Issue::UnknownBuiltin { word, name } => write!(f, "unknown built-in {:?} for {:?}", name, word),
+            Issue::InvalidWord(w) => write!(f, "{:?} is not a valid identifier", w),
+            Issue::UnknownMessage(c) => write!(f, "unknown message code: {}", c),
+        }
+    }
+}
+
+/// Az első sorbeli `// runa: locale=hu` pragma értéke.
+pub fn pragma_locale(src: &str) -> Option<&str> {
+    let line = src.lines().next()?.trim();
+    let rest = line.strip_prefix("//")?.trim_start().strip_prefix("runa:")?.trim_start();
+    let loc = rest.strip_prefix("locale=")?.trim();
+    (!loc.is_empty()).then_some(loc)
+}
+
+/// A `név.<lokálé>.rn` fájlnév lokálé része. Csak akkor lokálé, ha van hozzá csomag a keresési
+/// úton; különben a pont a név része (`util.v2.rn`).
+pub fn suffix_locale<'a>(path: &'a str, search: &SearchPath) -> Option<&'a str> {
+    let name = std::path::Path::new(path).file_name()?.to_str()?;
+    let stem = name.strip_suffix(".rn")?;
+    let (_, loc) = stem.rsplit_once('.')?;
+    (!loc.is_empty() && search.has(loc)).then_some(loc)
+}
+
+/// A fájl saját lokáléja: pragma, különben fájlnév-utótag. Ha a kettő ellentmond, hiba.
+pub fn detect_locale(path: &str, src: &str, search: &SearchPath) -> Result<Option<String>> {
+    match (pragma_locale(src), suffix_locale(path, search)) {
+        (Some(p), Some(s)) if p != s => Err(RunaError::Langpack(LangpackError::ConflictingLocale { pragma: p.to_string(), suffix: s.to_string() })),
+        (Some(l), _) | (None, Some(l)) => Ok(Some(l.to_string())),
+        (None, None) => Ok(None),
+    }
+}
+
+fn is_ident(w: &str) -> bool {
+    let mut chars = w.chars();
+    chars.next().is_some_and(|c| c == '_' || unicode_ident::is_xid_start(c)) && chars.all(unicode_ident::is_xid_continue)
+}
+
+/// JSON objektum kulcs-érték párjai sorrendben, duplikátumokkal együtt.
+fn entries<'de, D: Deserializer<'de>>(d: D) -> std::result::Result<Vec<(String, String)>, D::Error> {
+    struct Entries;
+    impl<'de> de::Visitor<'de> for Entries {
+        type Value = Vec<(String, String)>;
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str("an object of string -> string entries") }
+        fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> std::result::Result<Self::Value, A::Error> {
+            let mut out = Vec::new();
+            while let Some(entry) = map.next_entry()? { out.push(entry); }
+            Ok(out)
+        }
+    }
+    d.deserialize_map(Entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suffix_is_locale_only_with_pack() {
+        let search = SearchPath::new(Vec::new());
+        assert_eq!(suffix_locale("lib/matek.hu.rn", &search), Some("hu"));
+        assert_eq!(suffix_locale("my.module.rn", &search), None);
+        assert_eq!(detect_locale("util.v2.rn", "", &search).unwrap(), None);
+    }
+
+    #[test]
+    fn hungarian_words_are_accented_only() {
+        let hu = SearchPath::new(Vec::new()).load("hu").unwrap();
+        assert_eq!(hu.check(), Vec::new());
+        let map = hu.builtin_map().unwrap();
+        assert_eq!(map.get("kiír"), Some(&Builtin::Print));
+        assert!(!map.contains_key("kiir") && !map.contains_key("hozzafuz"));
+        for old in ["fuggveny", "valtozo", "amig", "kulonben"] { assert!(!hu.keywords.iter().any(|(w, _)| w == old), "{}", old); }
+    }
+}
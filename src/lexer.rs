@@ -1,7 +1,10 @@
-use anyhow::{anyhow, Result};
 use logos::Logos;
+use unicode_normalization::UnicodeNormalization;
 use std::collections::HashMap;
-use crate::token::TokenKind;
+use crate::diag::LineIndex;
+use crate::error::{LangpackError, LexError, Result, RunaError};
+use crate::langpack::Langpack;
+use crate::token::{Token, TokenKind};
 
 #[derive(Logos, Debug, PartialEq)]
 enum RawTok {
@@ -14,7 +17,9 @@ enum RawTok {
     Str,
     #[regex(r"[0-9]+")]
     Int,
-    #[regex(r"[A-Za-z_][A-Za-z0-9_]*")]
+    #[regex(r"[0-9]+\.[0-9]+([eE][+-]?[0-9]+)?|[0-9]+[eE][+-]?[0-9]+")]
+    Float,
+    #[regex(r"[\p{XID_Start}_][\p{XID_Continue}]*")]
     Word,
 
     #[token("(")] LParen,   #[token(")")] RParen,
@@ -29,100 +34,130 @@ enum RawTok {
     #[token("=")] Assign,   #[token("==")] Eq,  #[token("!=")] Ne,
     #[token("<")] Lt,       #[token("<=")] Le,
     #[token(">")] Gt,       #[token(">=")] Ge,
+    #[token("+=")] PlusAssign, #[token("-=")] MinusAssign, #[token("*=")] StarAssign,
+    #[token("/=")] SlashAssign, #[token("%=")] PercentAssign,
     #[token("&&")] AndAnd,  #[token("||")] OrOr, #[token("!")] Not,
 }
 
-pub struct Lexer { locale: HashMap<String, TokenKind> }
+/// `locale`: egyszavas kulcsszavak; `phrases`: több szóból álló kulcsszavak (pl. "else if"),
+/// ezekre a lexer a leghosszabb egyezést választja.
+pub struct Lexer { locale: HashMap<String, TokenKind>, phrases: HashMap<Vec<String>, TokenKind>, max_phrase: usize }
 
 impl Lexer {
-    pub fn from_locale_json(json: &str) -> Result<Self> {
-        let raw: HashMap<String, String> = serde_json::from_str(json)?;
+    pub fn new(lp: &Langpack) -> Result<Self> {
         let mut map = HashMap::new();
-        for (k, v) in raw {
-            let tk = match v.as_str() {
-                "KwClass" => TokenKind::KwClass,
-                "KwFn" => TokenKind::KwFn,
-                "KwIf" => TokenKind::KwIf,
-                "KwElse" => TokenKind::KwElse,
-                "KwReturn" => TokenKind::KwReturn,
-                "KwLet" => TokenKind::KwLet,
-                "KwVar" => TokenKind::KwVar,
-                "KwWhile" => TokenKind::KwWhile,
-                "KwFor" => TokenKind::KwFor,
-                "KwIn" => TokenKind::KwIn,
-                "KwBreak" => TokenKind::KwBreak,
-                "KwContinue" => TokenKind::KwContinue,
-                "KwTrue" => TokenKind::KwTrue,
-                "KwFalse" => TokenKind::KwFalse,
-                "KwVoid" => TokenKind::KwVoid,
-                other => return Err(anyhow!(format!("ismeretlen kulcsszó azonosító: {}", other))),
-            };
-            map.insert(k, tk);
+        let mut phrases = HashMap::new();
+        for (k, v) in &lp.keywords {
+            let tk = TokenKind::keyword(v).ok_or_else(|| RunaError::Langpack(LangpackError::UnknownKeywordKind(v.clone())))?;
+            let words: Vec<String> = k.split_whitespace().map(|w| w.nfc().collect()).collect();
+            if words.len() > 1 { phrases.insert(words, tk); } else { map.insert(k.nfc().collect::<String>(), tk); }
         }
-        Ok(Self { locale: map })
+        let max_phrase = phrases.keys().map(Vec::len).max().unwrap_or(1);
+        Ok(Self { locale: map, phrases, max_phrase })
     }
 
-    pub fn lex(&self, src: &str) -> Result<Vec<TokenKind>> {
+    pub fn lex(&self, src: &str) -> Result<Vec<Token>> { self.lex_file(src, 0) }
+
+    /// Mint a `lex`, de a spanek a `file` sorszámú forrásfájlra mutatnak.
+    pub fn lex_file(&self, src: &str, file: usize) -> Result<Vec<Token>> {
         let mut out = Vec::new();
+        let lines = LineIndex::new(src, file);
         let mut lexer = RawTok::lexer(src);
 
         while let Some(res) = lexer.next() {
-            match res {
+            let span = lines.span(src, lexer.span().start, lexer.span().end);
+            let kind = match res {
                 Ok(tok) => match tok {
-                    RawTok::Whitespace | RawTok::LineComment => {}
+                    RawTok::Whitespace | RawTok::LineComment => continue,
                     RawTok::Str => {
                         let slice = lexer.slice();
-                        let unq = unquote(slice)?;
-                        out.push(TokenKind::Str(unq));
+                        TokenKind::Str(unquote(slice).map_err(|kind| RunaError::Lex { kind, span })?)
                     }
                     RawTok::Int => {
-                        let n: i64 = lexer.slice().parse()?;
-                        out.push(TokenKind::Int(n));
+                        let n: i64 = lexer.slice().parse().map_err(|e: std::num::ParseIntError| RunaError::Lex { kind: LexError::BadIntLiteral(e.to_string()), span })?;
+                        TokenKind::Int(n)
                     }
-                    RawTok::Word => {
-                        let w = lexer.slice().to_string();
-                        if let Some(kw) = self.locale.get(&w) { out.push(kw.clone()); }
-                        else { out.push(TokenKind::Ident(w)); }
+                    RawTok::Float => {
+                        let x: f64 = lexer.slice().parse().map_err(|e: std::num::ParseFloatError| RunaError::Lex { kind: LexError::BadFloatLiteral(e.to_string()), span })?;
+                        if !x.is_finite() { return Err(RunaError::Lex { kind: LexError::BadFloatLiteral(format!("{} is out of range", lexer.slice())), span }); }
+                        TokenKind::Float(x)
                     }
-                    RawTok::LParen => out.push(TokenKind::LParen),
-                    RawTok::RParen => out.push(TokenKind::RParen),
-                    RawTok::LBrace => out.push(TokenKind::LBrace),
-                    RawTok::RBrace => out.push(TokenKind::RBrace),
-                    RawTok::LBracket => out.push(TokenKind::LBracket),
-                    RawTok::RBracket => out.push(TokenKind::RBracket),
-                    RawTok::Comma => out.push(TokenKind::Comma),
-                    RawTok::Dot => out.push(TokenKind::Dot),
-                    RawTok::Colon => out.push(TokenKind::Colon),
-                    RawTok::Semicolon => out.push(TokenKind::Semicolon),
-                    RawTok::Arrow => out.push(TokenKind::Arrow),
-                    RawTok::Plus => out.push(TokenKind::Plus),
-                    RawTok::Minus => out.push(TokenKind::Minus),
-                    RawTok::Star => out.push(TokenKind::Star),
-                    RawTok::Slash => out.push(TokenKind::Slash),
-                    RawTok::Percent => out.push(TokenKind::Percent),
-                    RawTok::Assign => out.push(TokenKind::Assign),
-                    RawTok::Eq => out.push(TokenKind::Eq),
-                    RawTok::Ne => out.push(TokenKind::Ne),
-                    RawTok::Lt => out.push(TokenKind::Lt),
-                    RawTok::Le => out.push(TokenKind::Le),
-                    RawTok::Gt => out.push(TokenKind::Gt),
-                    RawTok::Ge => out.push(TokenKind::Ge),
-                    RawTok::AndAnd => out.push(TokenKind::AndAnd),
-                    RawTok::OrOr => out.push(TokenKind::OrOr),
-                    RawTok::Not => out.push(TokenKind::Not),
+                    // NFC, hogy az előre komponált és a kombináló ékezetes alak ugyanaz legyen;
+                    // kulcsszó-e, azt a `keywords` menet dönti el
+                    RawTok::Word => TokenKind::Ident(lexer.slice().nfc().collect()),
+                    RawTok::LParen => TokenKind::LParen,
+                    RawTok::RParen => TokenKind::RParen,
+                    RawTok::LBrace => TokenKind::LBrace,
+                    RawTok::RBrace => TokenKind::RBrace,
+                    RawTok::LBracket => TokenKind::LBracket,
+                    RawTok::RBracket => TokenKind::RBracket,
+                    RawTok::Comma => TokenKind::Comma,
+                    RawTok::Dot => TokenKind::Dot,
+                    RawTok::Colon => TokenKind::Colon,
+                    RawTok::Semicolon => TokenKind::Semicolon,
+                    RawTok::Arrow => TokenKind::Arrow,
+                    RawTok::Plus => TokenKind::Plus,
+                    RawTok::Minus => TokenKind::Minus,
+                    RawTok::Star => TokenKind::Star,
+                    RawTok::Slash => TokenKind::Slash,
+                    RawTok::Percent => TokenKind::Percent,
+                    RawTok::Assign => TokenKind::Assign,
+                    RawTok::Eq => TokenKind::Eq,
+                    RawTok::Ne => TokenKind::Ne,
+                    RawTok::Lt => TokenKind::Lt,
+                    RawTok::Le => TokenKind::Le,
+                    RawTok::Gt => TokenKind::Gt,
+                    RawTok::Ge => TokenKind::Ge,
+                    RawTok::PlusAssign => TokenKind::PlusAssign,
+                    RawTok::MinusAssign => TokenKind::MinusAssign,
+                    RawTok::StarAssign => TokenKind::StarAssign,
+                    RawTok::SlashAssign => TokenKind::SlashAssign,
+                    RawTok::PercentAssign => TokenKind::PercentAssign,
+                    RawTok::AndAnd => TokenKind::AndAnd,
+                    RawTok::OrOr => TokenKind::OrOr,
+                    RawTok::Not => TokenKind::Not,
                 },
-                Err(()) => { return Err(anyhow!(format!("lexikai hiba: poz {}", lexer.span().start))); }
-            }
+                Err(()) => { return Err(RunaError::Lex { kind: LexError::UnexpectedChar(lexer.slice().to_string()), span }); }
+            };
+            out.push(Token { kind, span });
         }
 
-        out.push(TokenKind::Eof);
-        Ok(out)
+        out.push(Token { kind: TokenKind::Eof, span: lines.span(src, src.len(), src.len()) });
+        Ok(self.keywords(src, out))
+    }
+
+    /// Szavak kulcsszóvá alakítása. Csak whitespace-szel elválasztott szósorozat lehet
+    /// többszavas kulcsszó; a leghosszabb illeszkedő kifejezés nyer.
+    fn keywords(&self, src: &str, toks: Vec<Token>) -> Vec<Token> {
+        let mut out = Vec::with_capacity(toks.len());
+        let mut i = 0;
+        while i < toks.len() {
+            // a legfeljebb `max_phrase` hosszú, csak whitespace-szel tagolt szósorozat
+            let mut words: Vec<String> = Vec::new();
+            while words.len() < self.max_phrase && i + words.len() < toks.len() {
+                let j = i + words.len();
+                let TokenKind::Ident(w) = &toks[j].kind else { break };
+                if j > i && !src[toks[j - 1].span.end..toks[j].span.start].trim().is_empty() { break; }
+                words.push(w.clone());
+            }
+            let matched = (2..=words.len()).rev().find_map(|n| self.phrases.get(&words[..n]).map(|kw| (n, kw.clone())));
+            if let Some((n, kind)) = matched {
+                out.push(Token { kind, span: toks[i].span.to(toks[i + n - 1].span) });
+                i += n;
+                continue;
+            }
+            let mut tok = toks[i].clone();
+            if let TokenKind::Ident(w) = &tok.kind { if let Some(kw) = self.locale.get(w) { tok.kind = kw.clone(); } }
+            out.push(tok);
+            i += 1;
+        }
+        out
     }
 }
 
-fn unquote(s: &str) -> Result<String> {
+fn unquote(s: &str) -> std::result::Result<String, LexError> {
     let bytes = s.as_bytes();
-    if bytes.len() < 2 { return Err(anyhow!("rossz string literal")); }
+    if bytes.len() < 2 { return Err(LexError::BadStringLiteral); }
     let inner = &s[1..s.len()-1];
     let mut out = String::new();
     let mut chars = inner.chars();
@@ -135,9 +170,9 @@ fn unquote(s: &str) -> Result<String> {
                     'r' => out.push('\r'),
                     '\\' => out.push('\\'),
                     '"' => out.push('"'),
-                    other => return Err(anyhow!(format!("ismeretlen escape: \\{}", other))),
+                    other => return Err(LexError::UnknownEscape(other)),
                 }
-            } else { return Err(anyhow!("befejezetlen escape")); }
+            } else { return Err(LexError::UnterminatedEscape); }
         } else { out.push(c); }
     }
     Ok(out)
@@ -0,0 +1,106 @@
+use std::{collections::{HashMap, HashSet}, fs, path::{Path, PathBuf}};
+
+use crate::{
+    ast::{ImportDecl, Item},
+    codegen::{Codegen, Module},
+    error::{ResolveError, Result, RunaError},
+    ir::ProgramIR,
+    langpack::{self, Langpack, SearchPath},
+    lexer::Lexer,
+    parser::Parser,
+};
+
+/// Egy betöltött forrásfájl; a sorszáma a spanek `file` mezője.
+pub struct SourceFile { pub path: String, pub src: String, pub locale: String }
+
+/// Többfájlos program betöltése. Az `import` utakat az importáló fájlhoz képest oldja fel,
+/// minden modult egyszer, a saját lokáléjával (pragma / fájlnév, különben az importálóé) lexel és
+/// elemez, a köröket jelzi, végül a kódgenerátor egyetlen ProgramIR-ré linkeli a modulokat.
+pub struct Loader<'s> {
+    search: &'s SearchPath,
+    pub files: Vec<SourceFile>,
+    modules: Vec<Option<Module>>,
+    by_path: HashMap<PathBuf, usize>,
+    prefixes: HashSet<String>,
+    /// A betöltés alatt álló modulok láncolata (import-körök felismeréséhez).
+    loading: Vec<usize>,
+}
+
+impl<'s> Loader<'s> {
+    pub fn new(search: &'s SearchPath) -> Self {
+        Self { search, files: Vec::new(), modules: Vec::new(), by_path: HashMap::new(), prefixes: HashSet::new(), loading: Vec::new() }
+    }
+
+    /// A belépő fájl és az általa (közvetve) importált modulok lefordítása. Hiba esetén a
+    /// `files` a hibás fájlig bezárólag tartalmazza a forrásokat a megjelenítéshez; span nélküli
+    /// hiba az utolsó betöltött fájlhoz tartozik.
+    pub fn compile(&mut self, path: &str, src: String, locale: &str) -> Result<ProgramIR> {
+        self.load(path, src, locale.to_string(), String::new())?;
+        let modules: Vec<Module> = self.modules.drain(..).map(|m| m.expect("every module is loaded")).collect();
+        Codegen::new().build(&modules)
+    }
+
+    fn load(&mut self, path: &str, src: String, locale: String, prefix: String) -> Result<usize> {
+        let id = self.files.len();
+        if let Ok(canon) = fs::canonicalize(path) { self.by_path.insert(canon, id); }
+        self.files.push(SourceFile { path: path.to_string(), src, locale });
+        self.modules.push(None);
+        self.prefixes.insert(prefix.clone());
+
+        let lp: Langpack = self.search.load(&self.files[id].locale)?;
+        let toks = Lexer::new(&lp)?.lex_file(&self.files[id].src, id)?;
+        let program = Parser::new(toks).parse_program()?;
+
+        self.loading.push(id);
+        let mut imports: HashMap<String, usize> = HashMap::new();
+        for item in &program.items {
+            let Item::Import(imp) = item else { continue };
+            let target = self.import(id, imp)?;
+            let alias = module_name(&self.files[target].path, self.search);
+            match imports.insert(alias.clone(), target) {
+                Some(prev) if prev != target => return Err(RunaError::Resolve { kind: ResolveError::DuplicateImport(alias), span: imp.span }),
+                _ => {}
+            }
+        }
+        self.loading.pop();
+
+        self.modules[id] = Some(Module { prefix, program, builtins: lp.builtin_map()?, imports });
+        Ok(id)
+    }
+
+    fn import(&mut self, from: usize, imp: &ImportDecl) -> Result<usize> {
+        let not_found = || RunaError::Resolve { kind: ResolveError::ModuleNotFound(imp.path.clone()), span: imp.span };
+        let path = Path::new(&self.files[from].path).parent().unwrap_or(Path::new("")).join(&imp.path);
+        let canon = fs::canonicalize(&path).map_err(|_| not_found())?;
+        if let Some(&id) = self.by_path.get(&canon) {
+            if let Some(pos) = self.loading.iter().position(|&l| l == id) {
+                let chain: Vec<&str> = self.loading[pos..].iter().chain([&id]).map(|&l| self.files[l].path.as_str()).collect();
+                return Err(RunaError::Resolve { kind: ResolveError::ImportCycle(chain.join(" -> ")), span: imp.span });
+            }
+            return Ok(id);
+        }
+
+        let path = path.to_string_lossy().into_owned();
+        let src = fs::read_to_string(&path).map_err(|_| not_found())?;
+        let locale = match langpack::detect_locale(&path, &src, self.search) {
+            Ok(locale) => locale.unwrap_or_else(|| self.files[from].locale.clone()),
+            // a span nélküli hiba a modul saját fájljánál jelenjen meg (az utolsó betöltött fájl)
+            Err(e) => { self.files.push(SourceFile { path, src, locale: String::new() }); return Err(e); }
+        };
+        let name = module_name(&path, self.search);
+        let mut prefix = name.clone();
+        let mut n = 2;
+        while self.prefixes.contains(&prefix) { prefix = format!("{}#{}", name, n); n += 1; }
+        self.load(&path, src, locale, prefix)
+    }
+}
+
+/// A modul neve a forrásban: a fájlnév `.rn` és lokálé-utótag nélkül (`matek.hu.rn` -> `matek`).
+fn module_name(path: &str, search: &SearchPath) -> String {
+    let file = Path::new(path).file_name().and_then(|f| f.to_str()).unwrap_or(path);
+    let stem = file.strip_suffix(".rn").unwrap_or(file);
+    match langpack::suffix_locale(path, search) {
+        Some(loc) => stem.strip_suffix(loc).and_then(|s| s.strip_suffix('.')).unwrap_or(stem).to_string(),
+        None => stem.to_string(),
+    }
+}
@@ -1,69 +1,174 @@
-use anyhow::{anyhow, Result};
-use std::{env, fs};
+use std::{env, fs, path::PathBuf};
 
-mod token; mod lexer; mod ast; mod parser; mod ir; mod codegen; mod vm;
+mod token; mod diag; mod error; mod langpack; mod lexer; mod ast; mod parser; mod ir; mod codegen; mod loader; mod vm; mod gc; mod translate;
 
-use lexer::Lexer;
-use parser::Parser;
-use codegen::Codegen;
+use error::{CliError, LangpackError, RunaError};
+use langpack::{Langpack, SearchPath, Source};
+use loader::{Loader, SourceFile};
 use vm::VM;
 
-fn main() -> Result<()> {
-    // ---- args: --locale=<hu|en> --file=<path> ----
-    let mut locale = String::from("en");
+fn main() {
+    let (args, lp_dirs) = split_langpack_dirs(env::args().skip(1).collect());
+    let search = SearchPath::new(lp_dirs);
+    match args.first().map(String::as_str) {
+        Some("langpack") => return langpack_cmd(&search, &args[1..]),
+        Some("translate") => return translate_cmd(&search, &args[1..]),
+        _ => {}
+    }
+
+    // ---- args: --locale=<hu|en> --file=<path> --gc-stress ----
+    let mut locale: Option<String> = None;
     let mut file: Option<String> = None;
-    let args: Vec<String> = env::args().skip(1).collect();
+    let mut gc_stress = false;
     let mut i = 0;
     while i < args.len() {
         match args[i].as_str() {
-            "--locale" => { i += 1; locale = args.get(i).cloned().ok_or_else(|| anyhow!("--locale needs value"))?; }
-            "--file"   => { i += 1; file = Some(args.get(i).cloned().ok_or_else(|| anyhow!("--file needs value"))?); }
-            x if x.starts_with("--locale=") => { locale = x["--locale=".len()..].to_string(); }
+            "--locale" => { i += 1; locale = Some(args.get(i).cloned().unwrap_or_else(|| fail_cli(CliError::MissingValue("--locale".into()), &search, None))); }
+            "--file"   => { i += 1; file = Some(args.get(i).cloned().unwrap_or_else(|| fail_cli(CliError::MissingValue("--file".into()), &search, locale.as_deref()))); }
+            x if x.starts_with("--locale=") => { locale = Some(x["--locale=".len()..].to_string()); }
             x if x.starts_with("--file=")   => { file = Some(x["--file=".len()..].to_string()); }
-            other => return Err(anyhow!(format!("Unknown arg: {}", other))),
+            "--gc-stress" => gc_stress = true,
+            other => fail_cli(CliError::UnknownArg(other.to_string()), &search, locale.as_deref()),
         }
         i += 1;
     }
 
-    // ---- langpack betöltés ----
-    let lp_path = format!("langpacks/{}.json", locale);
-    let lp_json = fs::read_to_string(&lp_path)
-        .map_err(|e| anyhow!("Cannot read {}: {}", lp_path, e))?;
-    let lexer = Lexer::from_locale_json(&lp_json)?;
-
     // ---- forrás beolvasása vagy demó ----
-    let src = if let Some(p) = file {
-        fs::read_to_string(&p).map_err(|e| anyhow!("Cannot read source file: {}", e))?
+    let (path, src) = if let Some(p) = file {
+        let src = fs::read_to_string(&p)
+            .unwrap_or_else(|e| fail_cli(CliError::ReadFile { path: p.clone(), reason: e.to_string() }, &search, locale.as_deref()));
+        (p, src)
     } else {
-        default_demo(&locale).to_string()
+        let demo = default_demo(locale.as_deref().unwrap_or("en"));
+        ("<demo>".to_string(), demo.to_string())
     };
 
-    // ---- fordítási lánc ----
-    let toks = lexer.lex(&src)?;
-    let mut parser = Parser::new(toks);
-    let program = parser.parse_program()?;
+    // ---- lokálé: --locale, különben a fájl pragmája / utótagja, különben en ----
+    let locale = match locale {
+        Some(l) => l,
+        None => langpack::detect_locale(&path, &src, &search)
+            .unwrap_or_else(|e| fail(&e, &path, &src, &Langpack::default()))
+            .unwrap_or_else(|| "en".to_string()),
+    };
+    let lp = search.load(&locale).unwrap_or_else(|e| fail(&e, &path, &src, &Langpack::default()));
 
-    let ir = Codegen::new().build(&program)?;
-    let vm = VM::new(ir);
+    // ---- fordítási lánc (a belépő fájl és az importált modulok) ----
+    let mut loader = Loader::new(&search);
+    let ir = loader.compile(&path, src.clone(), &locale).unwrap_or_else(|e| match guess_locale(&search, &path, &src, &locale) {
+        Some(detected) => fail(&RunaError::Langpack(LangpackError::LocaleMismatch { used: locale.clone(), detected }), &path, &src, &lp),
+        None => fail_in(&e, &loader.files, loader.files.len() - 1, &lp),
+    });
+    let mut vm = VM::new(ir);
+    vm.set_gc_stress(gc_stress);
+    vm.set_langpack(lp.clone());
 
     // ---- belépési pont ----
-    let entries = if locale == "hu" { vec!["fo", "main"] } else { vec!["main", "fo"] };
-    for e in entries {
-        if let Ok(val) = vm.run(e) {
-            println!("{}() -> {:?}", e, val);
-            return Ok(());
+    let entries = if locale == "hu" { ["fo", "main"] } else { ["main", "fo"] };
+    let entry = entries.into_iter().find(|e| vm.has_function(e))
+        .unwrap_or_else(|| fail(&RunaError::Cli(CliError::NoEntry(entries.join("/"))), &path, &src, &lp));
+    let val = vm.run(entry).unwrap_or_else(|e| fail_in(&e, &loader.files, 0, &lp));
+    println!("{}() -> {:?}", entry, val);
+}
+
+/// Ha a forrás a megadott lokáléval nem fordul, de egy másik elérhetővel igen, az a valószínű szándék.
+fn guess_locale(search: &SearchPath, path: &str, src: &str, used: &str) -> Option<String> {
+    search.list().into_iter().map(|(l, _)| l).filter(|l| l != used)
+        .find(|l| Loader::new(search).compile(path, src.to_string(), l).is_ok())
+}
+
+/// A `--langpack-dir <dir>` kapcsolók bármelyik alparancs előtt/után állhatnak, ezért előre kiszedjük őket.
+fn split_langpack_dirs(args: Vec<String>) -> (Vec<String>, Vec<PathBuf>) {
+    let (mut rest, mut dirs) = (Vec::new(), Vec::new());
+    let mut it = args.into_iter();
+    while let Some(a) = it.next() {
+        match a.as_str() {
+            "--langpack-dir" => dirs.push(PathBuf::from(it.next().unwrap_or_else(|| fail_cli(CliError::MissingValue("--langpack-dir".into()), &SearchPath::new(Vec::new()), None)))),
+            x if x.starts_with("--langpack-dir=") => dirs.push(PathBuf::from(&x["--langpack-dir=".len()..])),
+            _ => rest.push(a),
         }
     }
-    Err(anyhow!("No entry function found (expected: main/fo)"))
+    (rest, dirs)
+}
+
+// ---- runa translate [--from=<locale>] --to=<locale> <file> ----
+fn translate_cmd(search: &SearchPath, args: &[String]) {
+    let (mut from, mut to, mut file) = (None, None, None);
+    for a in args {
+        match a.as_str() {
+            x if x.starts_with("--from=") => from = Some(x["--from=".len()..].to_string()),
+            x if x.starts_with("--to=") => to = Some(x["--to=".len()..].to_string()),
+            x if !x.starts_with("--") && file.is_none() => file = Some(x.to_string()),
+            other => fail_cli(CliError::UnknownArg(other.to_string()), search, None),
+        }
+    }
+    let (Some(to), Some(path)) = (to, file) else {
+        fail_cli(CliError::Usage("runa translate [--from=<locale>] --to=<locale> <file>".into()), search, None)
+    };
+    let src = fs::read_to_string(&path).unwrap_or_else(|e| fail_cli(CliError::ReadFile { path: path.clone(), reason: e.to_string() }, search, None));
+    let from = match from {
+        Some(f) => f,
+        None => langpack::detect_locale(&path, &src, search)
+            .unwrap_or_else(|e| fail(&e, &path, &src, &Langpack::default()))
+            .unwrap_or_else(|| fail_cli(CliError::UnknownSourceLocale(path.clone()), search, None)),
+    };
+    let load = |l: &str| search.load(l).unwrap_or_else(|e| fail(&e, &path, &src, &Langpack::default()));
+    let (from_lp, to_lp) = (load(&from), load(&to));
+    let out = translate::translate(&src, &from_lp, &to_lp).unwrap_or_else(|e| fail(&e, &path, &src, &from_lp));
+    print!("{}", translate::retag(&out, &to));
+}
+
+// ---- runa langpack check <file> | runa langpack list ----
+fn langpack_cmd(search: &SearchPath, args: &[String]) {
+    match args {
+        [cmd] if cmd == "list" => {
+            for (locale, source) in search.list() {
+                match source {
+                    Source::File(p) => println!("{}\t{}", locale, p.display()),
+                    Source::Bundled => println!("{}\t(bundled)", locale),
+                }
+            }
+        }
+        [cmd, path] if cmd == "check" => {
+            let json = fs::read_to_string(path).unwrap_or_else(|e| fail_cli(CliError::ReadFile { path: path.clone(), reason: e.to_string() }, search, None));
+            let issues = Langpack::from_json(&json).unwrap_or_else(|e| fail(&e, path, &json, &Langpack::default())).check();
+            for issue in &issues { println!("{}: {}", path, issue); }
+            if !issues.is_empty() { std::process::exit(1); }
+            println!("{}: ok", path);
+        }
+        _ => fail_cli(CliError::Usage("runa langpack check <file> | runa langpack list".into()), search, None),
+    }
+}
+
+/// Parancssori hiba, a `--locale`-lal megadott nyelven, ha az már ismert és betölthető.
+fn fail_cli(e: CliError, search: &SearchPath, locale: Option<&str>) -> ! {
+    let lp = locale.and_then(|l| search.load(l).ok()).unwrap_or_default();
+    eprint!("{}", RunaError::Cli(e).render("runa", "", &lp));
+    std::process::exit(1)
+}
+
+fn fail(e: &RunaError, path: &str, src: &str, lp: &Langpack) -> ! {
+    eprint!("{}", e.render(path, src, lp));
+    std::process::exit(1)
+}
+
+/// Többfájlos program hibája: a span fájljában, span nélkül a `default` fájlban jelenik meg.
+fn fail_in(e: &RunaError, files: &[SourceFile], default: usize, lp: &Langpack) -> ! {
+    let f = &files[e.span().map_or(default, |s| s.file)];
+    eprint!("{}", e.render(&f.path, &f.src, lp));
+    // más fájlban lévő megjegyzést (pl. importált modul deklarációja) a `render` nem ír ki
+    if let (Some(note), Some(span)) = (e.note(), e.span()) {
+        if note.file != span.file { let nf = &files[note.file]; eprint!("{}", e.render_note(&nf.path, &nf.src, lp)); }
+    }
+    std::process::exit(1)
 }
 
 // Kis beágyazott demó csak fallbacknek, lokálé szerint
 fn default_demo(locale: &str) -> &'static str {
     if locale == "hu" {
         r#"
-fuggveny fo() {
+függvény fo() {
   legyen a = [1,2,3];
-  kiir("len(a)=", len(a));
+  kiír("hossz(a)=", hossz(a));
   vissza a[0] + a[1] + a[2];
 }
 "#
@@ -1,27 +1,37 @@
-use anyhow::{anyhow, Result};
 use std::mem::discriminant;
 
 use crate::ast::*;
-use crate::token::TokenKind as T;
+use crate::error::{ParseError, Result, RunaError};
+use crate::token::{Span, Token, TokenKind as T};
 
-pub struct Parser { toks: Vec<T>, i: usize }
+pub struct Parser { toks: Vec<Token>, i: usize }
 impl Parser {
-    pub fn new(toks: Vec<T>) -> Self { Self { toks, i: 0 } }
+    pub fn new(toks: Vec<Token>) -> Self { Self { toks, i: 0 } }
 
     pub fn parse_program(&mut self) -> Result<Program> {
         let mut items = Vec::new();
         while !self.is(T::Eof) {
             if self.is(T::KwClass) { items.push(Item::Class(self.parse_class()?)); }
             else if self.is(T::KwFn) { items.push(Item::Func(self.parse_func()?)); }
-            else if self.is(T::KwLet) {
+            else if self.is(T::KwImport) {
+                let start = self.span();
+                self.bump();
+                let path = match self.peek().clone() {
+                    T::Str(s) => { self.bump(); s }
+                    other => return Err(RunaError::Parse { kind: ParseError::UnexpectedToken { expected: "string".to_string(), found: other.to_string() }, span: self.span() }),
+                };
+                self.expect(T::Semicolon)?;
+                items.push(Item::Import(ImportDecl { path, span: start.to(self.prev_span()) }));
+            }
+            else if self.is(T::KwLet) || self.is(T::KwVar) {
                 let decl = self.parse_let_decl()?;
                 self.expect(T::Semicolon)?;
                 items.push(Item::Let(decl));
             } else {
                 let stmt = self.parse_stmt()?;
-                match stmt {
-                    Stmt::Let(d) => items.push(Item::Let(d)),
-                    _ => return Err(anyhow!("Csak class/fn/let engedett a toplevelen")),
+                match stmt.kind {
+                    StmtKind::Let(d) => items.push(Item::Let(d)),
+                    _ => return Err(RunaError::Parse { kind: ParseError::TopLevelStmt, span: stmt.span }),
                 }
             }
         }
@@ -29,66 +39,91 @@ impl Parser {
     }
 
     fn parse_class(&mut self) -> Result<ClassDecl> {
+        let start = self.span();
         self.expect(T::KwClass)?;
         let name = self.expect_ident()?;
+        let parent = if self.eat(T::Colon) {
+            let start = self.span();
+            let mut p = self.expect_ident()?;
+            if self.eat(T::Dot) { p = format!("{}.{}", p, self.expect_ident()?); }
+            Some((p, start.to(self.prev_span())))
+        } else { None };
         self.expect(T::LBrace)?;
-        let mut methods = Vec::new();
-        while !self.is(T::RBrace) { self.expect(T::KwFn)?; methods.push(self.parse_func_after_kwfn()?); }
+        let (mut fields, mut methods) = (Vec::new(), Vec::new());
+        while !self.is(T::RBrace) {
+            let member_start = self.span();
+            if self.is(T::KwLet) || self.is(T::KwVar) {
+                let mutable = self.eat(T::KwVar);
+                if !mutable { self.bump(); }
+                let name = self.expect_ident()?;
+                let init = if self.eat(T::Assign) { Some(self.parse_expr()?) } else { None };
+                self.expect(T::Semicolon)?;
+                fields.push(FieldDecl { name, mutable, init, span: member_start.to(self.prev_span()) });
+                continue;
+            }
+            self.expect(T::KwFn)?;
+            methods.push(self.parse_func_after_kwfn(member_start)?);
+        }
         self.expect(T::RBrace)?;
-        Ok(ClassDecl { name, methods })
+        Ok(ClassDecl { name, parent, fields, methods, span: start.to(self.prev_span()) })
     }
 
-    fn parse_func(&mut self) -> Result<FuncDecl> { self.expect(T::KwFn)?; self.parse_func_after_kwfn() }
+    fn parse_func(&mut self) -> Result<FuncDecl> { let start = self.span(); self.expect(T::KwFn)?; self.parse_func_after_kwfn(start) }
 
-    fn parse_func_after_kwfn(&mut self) -> Result<FuncDecl> {
+    fn parse_func_after_kwfn(&mut self, start: Span) -> Result<FuncDecl> {
         let name = self.expect_ident()?;
+        let params = self.parse_params()?;
+        let body = self.parse_block()?;
+        Ok(FuncDecl { name, params, body, span: start.to(self.prev_span()) })
+    }
+
+    /// `(a, b, ...)`
+    fn parse_params(&mut self) -> Result<Vec<String>> {
         self.expect(T::LParen)?;
         let mut params = Vec::new();
         if !self.is(T::RParen) {
             loop { params.push(self.expect_ident()?); if self.eat(T::Comma) { continue; } break; }
         }
         self.expect(T::RParen)?;
-        let body = self.parse_block()?;
-        Ok(FuncDecl { name, params, body })
+        Ok(params)
     }
 
     fn parse_block(&mut self) -> Result<Block> {
+        let start = self.span();
         self.expect(T::LBrace)?;
         let mut stmts = Vec::new();
         while !self.is(T::RBrace) { stmts.push(self.parse_stmt()?); }
         self.expect(T::RBrace)?;
-        Ok(Block { stmts })
+        Ok(Block { stmts, span: start.to(self.prev_span()) })
     }
 
     fn parse_stmt(&mut self) -> Result<Stmt> {
-        if self.is(T::KwLet) {
+        let start = self.span();
+        let kind = self.parse_stmt_kind()?;
+        Ok(Stmt { kind, span: start.to(self.prev_span()) })
+    }
+
+    fn parse_stmt_kind(&mut self) -> Result<StmtKind> {
+        if self.is(T::KwLet) || self.is(T::KwVar) {
             let d = self.parse_let_decl()?;
             self.expect(T::Semicolon)?;
-            return Ok(Stmt::Let(d));
+            return Ok(StmtKind::Let(d));
         }
         if self.is(T::KwReturn) {
             self.bump();
-            if self.is(T::Semicolon) { self.bump(); return Ok(Stmt::Return(None)); }
+            if self.is(T::Semicolon) { self.bump(); return Ok(StmtKind::Return(None)); }
             let e = self.parse_expr()?;
             self.expect(T::Semicolon)?;
-            return Ok(Stmt::Return(Some(e)));
-        }
-        if self.is(T::KwIf) {
-            self.bump();
-            self.expect(T::LParen)?;
-            let cond = self.parse_expr()?;
-            self.expect(T::RParen)?;
-            let then_block = self.parse_block()?;
-            let else_block = if self.eat(T::KwElse) { Some(self.parse_block()?) } else { None };
-            return Ok(Stmt::If { cond, then_block, else_block });
+            return Ok(StmtKind::Return(Some(e)));
         }
+        if self.is(T::KwIf) { self.bump(); return self.parse_if_rest(); }
         if self.is(T::KwWhile) {
             self.bump();
             self.expect(T::LParen)?;
             let cond = self.parse_expr()?;
             self.expect(T::RParen)?;
             let body = self.parse_block()?;
-            return Ok(Stmt::While { cond, body });
+            return Ok(StmtKind::While { cond, body });
         }
         if self.is(T::KwFor) {
             self.bump();
@@ -98,43 +133,95 @@ impl Parser {
             let iter = self.parse_expr()?;
             self.expect(T::RParen)?;
             let body = self.parse_block()?;
-            return Ok(Stmt::ForIn { var, iter, body });
+            return Ok(StmtKind::ForIn { var, iter, body });
         }
-        if self.is(T::KwBreak) { self.bump(); self.expect(T::Semicolon)?; return Ok(Stmt::Break); }
-        if self.is(T::KwContinue) { self.bump(); self.expect(T::Semicolon)?; return Ok(Stmt::Continue); }
-
-        if matches!(self.peek(), T::Ident(_)) && self.kind_eq(self.peek_n(1), &T::Assign) {
-            let name = if let T::Ident(s) = self.peek().clone() { s } else { unreachable!() };
+        if self.is(T::KwTry) {
             self.bump();
-            self.expect(T::Assign)?;
-            let value = self.parse_expr()?;
-            self.expect(T::Semicolon)?;
-            return Ok(Stmt::Assign { name, value });
+            let body = self.parse_block()?;
+            self.expect(T::KwCatch)?;
+            self.expect(T::LParen)?;
+            let var = self.expect_ident()?;
+            self.expect(T::RParen)?;
+            let handler = self.parse_block()?;
+            return Ok(StmtKind::Try { body, var, handler });
         }
+        if self.is(T::KwBreak) { self.bump(); self.expect(T::Semicolon)?; return Ok(StmtKind::Break); }
+        if self.is(T::KwContinue) { self.bump(); self.expect(T::Semicolon)?; return Ok(StmtKind::Continue); }
+
         let e = self.parse_expr()?;
+        let op = match self.peek() {
+            T::Assign => None,
+            T::PlusAssign => Some(BinOp::Add),
+            T::MinusAssign => Some(BinOp::Sub),
+            T::StarAssign => Some(BinOp::Mul),
+            T::SlashAssign => Some(BinOp::Div),
+            T::PercentAssign => Some(BinOp::Mod),
+            _ => { self.expect(T::Semicolon)?; return Ok(StmtKind::Expr(e)); }
+        };
+        if !is_place(&e) { return Err(RunaError::Parse { kind: ParseError::InvalidAssignTarget, span: e.span }); }
+        self.bump();
+        let value = self.parse_expr()?;
         self.expect(T::Semicolon)?;
-        Ok(Stmt::Expr(e))
+        Ok(StmtKind::Assign { target: e, op, value })
     }
 
+    /// Az `if` kulcsszó utáni rész; az `else if` lánc egyelemű else-blokkba ágyazott If lesz.
+    fn parse_if_rest(&mut self) -> Result<StmtKind> {
+        self.expect(T::LParen)?;
+        let cond = self.parse_expr()?;
+        self.expect(T::RParen)?;
+        let then_block = self.parse_block()?;
+        // `else if` jöhet két külön kulcsszóként vagy a langpack többszavas kulcsszavaként (KwElseIf)
+        let else_block = if self.is(T::KwElseIf) || (self.is(T::KwElse) && self.kind_eq(self.peek_n(1), &T::KwIf)) {
+            let start = self.span();
+            if !self.eat(T::KwElseIf) { self.bump(); self.bump(); }
+            let kind = self.parse_if_rest()?;
+            let span = start.to(self.prev_span());
+            Some(Block { stmts: vec![Stmt { kind, span }], span })
+        } else if self.eat(T::KwElse) { Some(self.parse_block()?) } else { None };
+        Ok(StmtKind::If { cond, then_block, else_block })
+    }
+
+    /// `let x = e` vagy `var x = e`.
     fn parse_let_decl(&mut self) -> Result<LetDecl> {
-        self.expect(T::KwLet)?;
+        let start = self.span();
+        let mutable = self.eat(T::KwVar);
+        if !mutable { self.expect(T::KwLet)?; }
         let name = self.expect_ident()?;
         self.expect(T::Assign)?;
         let init = self.parse_expr()?;
-        Ok(LetDecl { name, init })
+        Ok(LetDecl { name, mutable, init, span: start.to(self.prev_span()) })
     }
 
-    fn parse_expr(&mut self) -> Result<Expr> { self.parse_equality() }
+    fn parse_expr(&mut self) -> Result<Expr> { self.parse_or() }
+
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut left = self.parse_and()?;
+        while self.eat(T::OrOr) {
+            let right = self.parse_and()?;
+            left = binary(BinOp::Or, left, right);
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut left = self.parse_equality()?;
+        while self.eat(T::AndAnd) {
+            let right = self.parse_equality()?;
+            left = binary(BinOp::And, left, right);
+        }
+        Ok(left)
+    }
 
     fn parse_equality(&mut self) -> Result<Expr> {
         let mut left = self.parse_comparison()?;
         loop {
             if self.eat(T::Eq) {
                 let right = self.parse_comparison()?;
-                left = Expr::Binary { op: BinOp::Eq, left: Box::new(left), right: Box::new(right) };
+                left = binary(BinOp::Eq, left, right);
             } else if self.eat(T::Ne) {
                 let right = self.parse_comparison()?;
-                left = Expr::Binary { op: BinOp::Ne, left: Box::new(left), right: Box::new(right) };
+                left = binary(BinOp::Ne, left, right);
             } else { break; }
         }
         Ok(left)
@@ -145,16 +232,16 @@ impl Parser {
         loop {
             if self.eat(T::Lt) {
                 let right = self.parse_term()?;
-                left = Expr::Binary { op: BinOp::Lt, left: Box::new(left), right: Box::new(right) };
+                left = binary(BinOp::Lt, left, right);
             } else if self.eat(T::Le) {
                 let right = self.parse_term()?;
-                left = Expr::Binary { op: BinOp::Le, left: Box::new(left), right: Box::new(right) };
+                left = binary(BinOp::Le, left, right);
             } else if self.eat(T::Gt) {
                 let right = self.parse_term()?;
-                left = Expr::Binary { op: BinOp::Gt, left: Box::new(left), right: Box::new(right) };
+                left = binary(BinOp::Gt, left, right);
             } else if self.eat(T::Ge) {
                 let right = self.parse_term()?;
-                left = Expr::Binary { op: BinOp::Ge, left: Box::new(left), right: Box::new(right) };
+                left = binary(BinOp::Ge, left, right);
             } else { break; }
         }
         Ok(left)
@@ -165,56 +252,105 @@ impl Parser {
         loop {
             if self.eat(T::Plus) {
                 let right = self.parse_factor()?;
-                left = Expr::Binary { op: BinOp::Add, left: Box::new(left), right: Box::new(right) };
+                left = binary(BinOp::Add, left, right);
             } else if self.eat(T::Minus) {
                 let right = self.parse_factor()?;
-                left = Expr::Binary { op: BinOp::Sub, left: Box::new(left), right: Box::new(right) };
+                left = binary(BinOp::Sub, left, right);
             } else { break; }
         }
         Ok(left)
     }
 
     fn parse_factor(&mut self) -> Result<Expr> {
-        let mut left = self.parse_postfix()?;
+        let mut left = self.parse_unary()?;
         loop {
             if self.eat(T::Star) {
-                let right = self.parse_postfix()?;
-                left = Expr::Binary { op: BinOp::Mul, left: Box::new(left), right: Box::new(right) };
+                let right = self.parse_unary()?;
+                left = binary(BinOp::Mul, left, right);
             } else if self.eat(T::Slash) {
-                let right = self.parse_postfix()?;
-                left = Expr::Binary { op: BinOp::Div, left: Box::new(left), right: Box::new(right) };
+                let right = self.parse_unary()?;
+                left = binary(BinOp::Div, left, right);
+            } else if self.eat(T::Percent) {
+                let right = self.parse_unary()?;
+                left = binary(BinOp::Mod, left, right);
             } else { break; }
         }
         Ok(left)
     }
 
+    fn parse_unary(&mut self) -> Result<Expr> {
+        let start = self.span();
+        let op = if self.eat(T::Minus) { UnOp::Neg } else if self.eat(T::Not) { UnOp::Not } else { return self.parse_postfix(); };
+        let expr = self.parse_unary()?;
+        let span = start.to(expr.span);
+        Ok(Expr { kind: ExprKind::Unary { op, expr: Box::new(expr) }, span })
+    }
+
     fn parse_postfix(&mut self) -> Result<Expr> {
         let mut expr = self.parse_primary()?;
         loop {
             if self.eat(T::LParen) {
-                let mut args = Vec::new();
-                if !self.is(T::RParen) {
-                    loop { args.push(self.parse_expr()?); if self.eat(T::Comma) { continue; } break; }
-                }
-                self.expect(T::RParen)?;
-                expr = Expr::Call { callee: Box::new(expr), args };
+                let args = self.parse_args()?;
+                let span = expr.span.to(self.prev_span());
+                expr = Expr { kind: ExprKind::Call { callee: Box::new(expr), args }, span };
             } else if self.eat(T::LBracket) {
-                let idx = self.parse_expr()?;
+                let (span, target) = (expr.span, Box::new(expr));
+                // `a[i]`, vagy szelet: `a[i:j]`, `a[:j]`, `a[i:]`
+                let idx = if self.is(T::Colon) { None } else { Some(self.parse_expr()?) };
+                let kind = if self.eat(T::Colon) {
+                    let end = if self.is(T::RBracket) { None } else { Some(Box::new(self.parse_expr()?)) };
+                    ExprKind::Slice { target, start: idx.map(Box::new), end }
+                } else {
+                    let Some(idx) = idx else { return Err(RunaError::Parse { kind: ParseError::ExpectedExpr(self.peek().to_string()), span: self.span() }) };
+                    ExprKind::Index { target, index: Box::new(idx) }
+                };
                 self.expect(T::RBracket)?;
-                expr = Expr::Index { target: Box::new(expr), index: Box::new(idx) };
+                expr = Expr { kind, span: span.to(self.prev_span()) };
+            } else if self.eat(T::Dot) {
+                let name = self.expect_ident()?;
+                let span = expr.span.to(self.prev_span());
+                expr = Expr { kind: ExprKind::Field { target: Box::new(expr), name }, span };
             } else { break; }
         }
         Ok(expr)
     }
 
+    /// Hívás argumentumai a nyitó zárójel után, a záró zárójellel együtt.
+    fn parse_args(&mut self) -> Result<Vec<Expr>> {
+        let mut args = Vec::new();
+        if !self.is(T::RParen) {
+            loop { args.push(self.parse_expr()?); if self.eat(T::Comma) { continue; } break; }
+        }
+        self.expect(T::RParen)?;
+        Ok(args)
+    }
+
     fn parse_primary(&mut self) -> Result<Expr> {
-        match self.peek().clone() {
-            T::Ident(name) => { self.bump(); Ok(Expr::Ident(name)) }
-            T::Int(n) => { self.bump(); Ok(Expr::Int(n)) }
-            T::Str(s) => { self.bump(); Ok(Expr::Str(s)) }
-            T::KwTrue => { self.bump(); Ok(Expr::Bool(true)) }
-            T::KwFalse => { self.bump(); Ok(Expr::Bool(false)) }
-            T::LParen => { self.bump(); let e = self.parse_expr()?; self.expect(T::RParen)?; Ok(Expr::Group(Box::new(e))) }
+        let start = self.span();
+        let kind = match self.peek().clone() {
+            T::Ident(name) => { self.bump(); ExprKind::Ident(name) }
+            T::Int(n) => { self.bump(); ExprKind::Int(n) }
+            T::Float(x) => { self.bump(); ExprKind::Float(x) }
+            T::Str(s) => { self.bump(); ExprKind::Str(s) }
+            T::KwTrue => { self.bump(); ExprKind::Bool(true) }
+            T::KwFalse => { self.bump(); ExprKind::Bool(false) }
+            T::KwVoid => { self.bump(); ExprKind::Void }
+            T::KwSelf => { self.bump(); ExprKind::SelfRef }
+            T::KwSuper => {
+                self.bump();
+                self.expect(T::Dot)?;
+                let method = self.expect_ident()?;
+                self.expect(T::LParen)?;
+                let args = self.parse_args()?;
+                ExprKind::SuperCall { method, args }
+            }
+            T::LParen => { self.bump(); let e = self.parse_expr()?; self.expect(T::RParen)?; ExprKind::Group(Box::new(e)) }
+            T::KwFn => {
+                self.bump();
+                let params = self.parse_params()?;
+                let body = self.parse_block()?;
+                ExprKind::Lambda { params, body }
+            }
             T::LBracket => {
                 self.bump();
                 let mut elems = Vec::new();
@@ -222,21 +358,53 @@ impl Parser {
                     loop { elems.push(self.parse_expr()?); if self.eat(T::Comma) { continue; } break; }
                 }
                 self.expect(T::RBracket)?;
-                Ok(Expr::Array(elems))
+                ExprKind::Array(elems)
             }
-            other => Err(anyhow!(format!("Várt elsődleges kifejezés, kaptam: {:?}", other))),
-        }
+            T::LBrace => {
+                self.bump();
+                let mut entries = Vec::new();
+                if !self.is(T::RBrace) {
+                    loop {
+                        let k = self.parse_expr()?;
+                        self.expect(T::Colon)?;
+                        entries.push((k, self.parse_expr()?));
+                        if self.eat(T::Comma) { continue; }
+                        break;
+                    }
+                }
+                self.expect(T::RBrace)?;
+                ExprKind::Map(entries)
+            }
+            other => return Err(RunaError::Parse { kind: ParseError::ExpectedExpr(other.to_string()), span: start }),
+        };
+        Ok(Expr { kind, span: start.to(self.prev_span()) })
     }
 
     // helpers
-    fn peek(&self) -> &T { self.toks.get(self.i).unwrap_or(&T::Eof) }
-    fn peek_n(&self, n: usize) -> &T { self.toks.get(self.i + n).unwrap_or(&T::Eof) }
+    fn peek(&self) -> &T { self.toks.get(self.i).map(|t| &t.kind).unwrap_or(&T::Eof) }
+    fn peek_n(&self, n: usize) -> &T { self.toks.get(self.i + n).map(|t| &t.kind).unwrap_or(&T::Eof) }
+    fn span(&self) -> Span { self.toks.get(self.i).or(self.toks.last()).map(|t| t.span).unwrap_or_default() }
+    fn prev_span(&self) -> Span { self.i.checked_sub(1).and_then(|j| self.toks.get(j)).map(|t| t.span).unwrap_or_default() }
     fn is(&self, k: T) -> bool { discriminant(self.peek()) == discriminant(&k) }
     fn kind_eq(&self, a: &T, b: &T) -> bool { discriminant(a) == discriminant(b) }
     fn eat(&mut self, k: T) -> bool { if self.is(k) { self.i += 1; true } else { false } }
-    fn expect(&mut self, k: T) -> Result<()> { if self.eat(k.clone()) { Ok(()) } else { Err(anyhow!(format!("Várt token: {:?}, kaptam: {:?}", k, self.peek()))) } }
+    fn expect(&mut self, k: T) -> Result<()> { if self.eat(k.clone()) { Ok(()) } else { Err(RunaError::Parse { kind: ParseError::UnexpectedToken { expected: k.to_string(), found: self.peek().to_string() }, span: self.span() }) } }
     fn bump(&mut self) { self.i += 1; }
     fn expect_ident(&mut self) -> Result<String> {
-        match self.peek().clone() { T::Ident(s) => { self.bump(); Ok(s) }, other => Err(anyhow!(format!("Várt azonosító, kaptam: {:?}", other))) }
+        match self.peek().clone() { T::Ident(s) => { self.bump(); Ok(s) }, other => Err(RunaError::Parse { kind: ParseError::ExpectedIdent(other.to_string()), span: self.span() }) }
     }
 }
+
+/// Értékadás bal oldala lehet-e: azonosító, vagy azonosítóból / `self`-ből induló index- és mezőlánc.
+fn is_place(e: &Expr) -> bool {
+    match &e.kind {
+        ExprKind::Ident(_) => true,
+        ExprKind::Index { target, .. } | ExprKind::Field { target, .. } => is_place(target) || matches!(target.kind, ExprKind::SelfRef),
+        _ => false,
+    }
+}
+
+fn binary(op: BinOp, left: Expr, right: Expr) -> Expr {
+    let span = left.span.to(right.span);
+    Expr { kind: ExprKind::Binary { op, left: Box::new(left), right: Box::new(right) }, span }
+}
@@ -1,13 +1,65 @@
-#[derive(Debug, Clone, PartialEq, Eq)]
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum TokenKind {
-    KwClass, KwFn, KwIf, KwElse, KwReturn, KwLet, KwVar,
+    KwClass, KwFn, KwIf, KwElse, KwElseIf, KwReturn, KwLet, KwVar,
     KwWhile, KwFor, KwIn, KwBreak, KwContinue,
-    KwTrue, KwFalse, KwVoid,
-    Ident(String), Int(i64), Str(String),
+    KwTrue, KwFalse, KwVoid, KwSelf, KwSuper, KwImport, KwTry, KwCatch,
+    Ident(String), Int(i64), Float(f64), Str(String),
     LParen, RParen, LBrace, RBrace, LBracket, RBracket,
     Comma, Dot, Colon, Semicolon, Arrow,
     Plus, Minus, Star, Slash, Percent,
     Assign, Eq, Ne, Lt, Le, Gt, Ge,
+    PlusAssign, MinusAssign, StarAssign, SlashAssign, PercentAssign,
     AndAnd, OrOr, Not,
     Eof,
 }
+
+/// Az összes kulcsszó-fajta a langpackben használt nevével; minden langpacknek mindet le kell fednie.
+pub const KEYWORDS: &[(&str, TokenKind)] = &[
+    ("KwClass", TokenKind::KwClass), ("KwFn", TokenKind::KwFn), ("KwIf", TokenKind::KwIf),
+    ("KwElse", TokenKind::KwElse), ("KwElseIf", TokenKind::KwElseIf), ("KwReturn", TokenKind::KwReturn),
+    ("KwLet", TokenKind::KwLet), ("KwVar", TokenKind::KwVar), ("KwWhile", TokenKind::KwWhile),
+    ("KwFor", TokenKind::KwFor), ("KwIn", TokenKind::KwIn), ("KwBreak", TokenKind::KwBreak),
+    ("KwContinue", TokenKind::KwContinue), ("KwTrue", TokenKind::KwTrue), ("KwFalse", TokenKind::KwFalse),
+    ("KwVoid", TokenKind::KwVoid), ("KwSelf", TokenKind::KwSelf), ("KwSuper", TokenKind::KwSuper),
+    ("KwImport", TokenKind::KwImport), ("KwTry", TokenKind::KwTry), ("KwCatch", TokenKind::KwCatch),
+];
+
+impl TokenKind {
+    pub fn keyword(name: &str) -> Option<TokenKind> { KEYWORDS.iter().find(|(n, _)| *n == name).map(|(_, k)| k.clone()) }
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use TokenKind::*;
+        let sym = match self {
+            Ident(s) => return write!(f, "`{}`", s),
+            Int(n) => return write!(f, "`{}`", n),
+            Float(x) => return write!(f, "`{:?}`", x),
+            Str(s) => return write!(f, "{:?}", s),
+            Eof => return write!(f, "EOF"),
+            LParen => "(", RParen => ")", LBrace => "{", RBrace => "}", LBracket => "[", RBracket => "]",
+            Comma => ",", Dot => ".", Colon => ":", Semicolon => ";", Arrow => "->",
+            Plus => "+", Minus => "-", Star => "*", Slash => "/", Percent => "%",
+            Assign => "=", Eq => "==", Ne => "!=", Lt => "<", Le => "<=", Gt => ">", Ge => ">=",
+            PlusAssign => "+=", MinusAssign => "-=", StarAssign => "*=", SlashAssign => "/=", PercentAssign => "%=",
+            AndAnd => "&&", OrOr => "||", Not => "!",
+            kw => return write!(f, "{:?}", kw),
+        };
+        write!(f, "`{}`", sym)
+    }
+}
+
+/// Bájt-tartomány a forrásban, plusz az első bájt 1-alapú sora és oszlopa (karakterben).
+/// `file`: a forrásfájl sorszáma a betöltött modulok között (0: a belépő fájl).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span { pub start: usize, pub end: usize, pub line: u32, pub col: u32, pub file: usize }
+
+impl Span {
+    /// `self` elejétől `other` végéig tartó span.
+    pub fn to(self, other: Span) -> Span { Span { end: other.end.max(self.start), ..self } }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token { pub kind: TokenKind, pub span: Span }
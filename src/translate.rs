@@ -0,0 +1,170 @@
+use std::collections::{HashMap, HashSet};
+
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{
+    error::{LangpackError, ResolveError, Result, RunaError},
+    ir::Builtin,
+    langpack::{self, Langpack},
+    lexer::Lexer,
+    token::{Token, TokenKind, KEYWORDS},
+};
+
+/// Forrás átírása egyik lokáléból a másikba: csak a kulcsszavak és a lokalizált beépített
+/// függvénynevek cserélődnek, minden más (whitespace, megjegyzés, azonosító) bájtra azonos marad.
+pub fn translate(src: &str, from: &Langpack, to: &Langpack) -> Result<String> {
+    let toks = Lexer::new(from)?.lex(src)?;
+    let from_builtins = from.builtin_map()?;
+    let to_builtins: HashMap<Builtin, String> = to.builtins.iter().rev()
+        .filter_map(|(w, n)| Builtin::from_name(n).map(|b| (b, w.nfc().collect())))
+        .collect();
+    // a fájlban kötött nevek (függvény, változó, paraméter) elfedik a beépítetteket, ezeket nem
+    // szabad átnevezni; `.` után tag (mező, metódus), `.` előtt modulnév áll, az sem beépített.
+    // A beépített hívásban és értékként (`map(xs, len)`) is cserélődik.
+    let bound = bound_names(&toks);
+
+    let mut out = String::with_capacity(src.len());
+    let mut last = 0;
+    for (i, tok) in toks.iter().enumerate() {
+        let replacement = match &tok.kind {
+            TokenKind::Ident(name) if !bound.contains(name.as_str())
+                && !matches!(toks.get(i + 1).map(|t| &t.kind), Some(TokenKind::Dot))
+                && !matches!(i.checked_sub(1).map(|j| &toks[j].kind), Some(TokenKind::Dot)) => {
+                match from_builtins.get(name).map(|b| to_builtins.get(b).cloned().unwrap_or_else(|| b.name().to_string())) {
+                    // a célnév a fájlban már foglalt: átnevezés után a felhasználói nevet hívná
+                    Some(word) if bound.contains(word.as_str()) => {
+                        return Err(RunaError::Resolve { kind: ResolveError::BuiltinCollision(word), span: tok.span });
+                    }
+                    word => word,
+                }
+            }
+            kind => match KEYWORDS.iter().find(|(_, k)| k == kind) {
+                Some((kind_name, _)) => Some(respace(&src[tok.span.start..tok.span.end], &keyword_word(to, kind_name)?)),
+                None => None,
+            },
+        };
+        if let Some(word) = replacement {
+            out.push_str(&src[last..tok.span.start]);
+            out.push_str(&word);
+            last = tok.span.end;
+        }
+    }
+    out.push_str(&src[last..]);
+
+    check_roundtrip(&toks, &Lexer::new(to)?.lex(&out)?)?;
+    Ok(out)
+}
+
+/// Az első sorbeli `// runa: locale=...` pragma átírása a cél lokáléra (a megjegyzést a
+/// `translate` változatlanul hagyja, így különben a régi lokálét hirdetné).
+pub fn retag(out: &str, locale: &str) -> String {
+    match langpack::pragma_locale(out) {
+        Some(old) => {
+            let line_end = out.find('\n').unwrap_or(out.len());
+            let first = &out[..line_end];
+            let at = first.rfind(old).expect("pragma value is on the first line");
+            format!("{}{}{}", &first[..at], locale, &out[at + old.len()..])
+        }
+        None => out.to_string(),
+    }
+}
+
+/// A fájlban kötött nevek: `let`/`var`/`fn`/`class` utáni név, függvényparaméter, `for` és
+/// `catch` változója. Hatókört nem követ: ami bárhol kötött, az mindenhol felhasználói névnek számít.
+fn bound_names(toks: &[Token]) -> HashSet<&str> {
+    let ident = |j: usize| match toks.get(j).map(|t| &t.kind) { Some(TokenKind::Ident(n)) => Some(n.as_str()), _ => None };
+    let mut bound = HashSet::new();
+    for (i, tok) in toks.iter().enumerate() {
+        match tok.kind {
+            TokenKind::KwLet | TokenKind::KwVar | TokenKind::KwClass => bound.extend(ident(i + 1)),
+            TokenKind::KwFn => {
+                let mut j = i + 1;
+                if let Some(n) = ident(j) { bound.insert(n); j += 1; }
+                if !matches!(toks.get(j).map(|t| &t.kind), Some(TokenKind::LParen)) { continue; }
+                j += 1;
+                while let Some(kind) = toks.get(j).map(|t| &t.kind) {
+                    match kind {
+                        TokenKind::Ident(n) => { bound.insert(n.as_str()); }
+                        TokenKind::Comma => {}
+                        _ => break,
+                    }
+                    j += 1;
+                }
+            }
+            TokenKind::KwFor | TokenKind::KwCatch if matches!(toks.get(i + 1).map(|t| &t.kind), Some(TokenKind::LParen)) => bound.extend(ident(i + 2)),
+            _ => {}
+        }
+    }
+    bound
+}
+
+/// Többszavas kulcsszónál a forrásbeli szavak közti whitespace megmarad, csak a szavak cserélődnek;
+/// ha a célkifejezés több szóból áll, a fölös helyekre egy szóköz kerül.
+fn respace(orig: &str, word: &str) -> String {
+    let mut gaps = orig.split(|c: char| !c.is_whitespace()).filter(|g| !g.is_empty());
+    let mut out = String::new();
+    for (i, w) in word.split(' ').enumerate() {
+        if i > 0 { out.push_str(gaps.next().unwrap_or(" ")); }
+        out.push_str(w);
+    }
+    out
+}
+
+fn keyword_word(lp: &Langpack, kind: &str) -> Result<String> {
+    lp.keywords.iter().find(|(_, k)| k == kind).map(|(w, _)| w.split_whitespace().collect::<Vec<_>>().join(" "))
+        .ok_or_else(|| RunaError::Langpack(LangpackError::MissingKeyword(kind.to_string())))
+}
+
+/// A lefordított szöveget a cél-langpackkel újralexelve ugyanazt a szerkezetet kell kapnunk;
+/// ha egy azonosító a célnyelvben kulcsszó, itt derül ki.
+fn check_roundtrip(before: &[Token], after: &[Token]) -> Result<()> {
+    match before.iter().zip(after).find(|(a, b)| matches!(a.kind, TokenKind::Ident(_)) && !matches!(b.kind, TokenKind::Ident(_))) {
+        Some((Token { kind: TokenKind::Ident(name), span }, _)) => Err(RunaError::Resolve { kind: ResolveError::KeywordCollision(name.clone()), span: *span }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packs() -> (Langpack, Langpack) {
+        (Langpack::from_json(include_str!("../langpacks/en.json")).unwrap(), Langpack::from_json(include_str!("../langpacks/hu.json")).unwrap())
+    }
+
+    #[test]
+    fn local_binding_shadows_builtin() {
+        let (en, hu) = packs();
+        let out = translate("fn main() { let len = fn(x) { return 0; }; print(len([1, 2])); }", &en, &hu).unwrap();
+        assert_eq!(out, "függvény main() { legyen len = függvény(x) { vissza 0; }; kiír(len([1, 2])); }");
+    }
+
+    #[test]
+    fn param_shadows_builtin() {
+        let (en, hu) = packs();
+        let out = translate("fn f(len) { return len(1); }", &en, &hu).unwrap();
+        assert!(out.contains("len(1)"), "{}", out);
+    }
+
+    #[test]
+    fn target_builtin_name_declared_is_rejected() {
+        let (en, hu) = packs();
+        let err = translate("fn hossz(a) { return 42; }\nfn main() { print(len([1, 2])); }", &en, &hu).unwrap_err();
+        assert!(matches!(&err, RunaError::Resolve { kind: ResolveError::BuiltinCollision(n), .. } if n == "hossz"), "{:?}", err);
+        assert_eq!(err.code(), "E0321");
+    }
+
+    #[test]
+    fn builtin_as_value_is_translated() {
+        let (en, hu) = packs();
+        let out = translate("fn main() { let f = upper; return map([\"a\"], len); }", &en, &hu).unwrap();
+        assert_eq!(out, "függvény main() { legyen f = nagybetűs; vissza leképez([\"a\"], hossz); }");
+    }
+
+    #[test]
+    fn phrase_keeps_whitespace() {
+        let (en, hu) = packs();
+        let out = translate("fn f(x) { if (x) { return 1; } else\n  if (x) { return 2; } return 3; }", &en, &hu).unwrap();
+        assert!(out.contains("} különben\n  ha ("), "{}", out);
+    }
+}
@@ -1,161 +1,1218 @@
-use std::collections::HashMap;
-use anyhow::{anyhow, Result};
+use std::{cell::{Cell, RefCell}, collections::{HashMap, HashSet}, fmt, rc::Rc};
+use crate::error::{Result, RunaError, RuntimeError};
+use crate::gc::{Heap, Marker};
+use crate::langpack::Langpack;
 use crate::ir::*;
+use crate::token::Span;
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub enum Value {
     Int(i64),
+    Float(f64),
+    /// Csak a `bigint` feature-rel: az i64-be nem férő egész.
+    #[cfg(feature = "bigint")]
+    BigInt(num_bigint::BigInt),
     Str(String),
     Bool(bool),
-    Array(Vec<Value>),
+    /// A tömb, a map és az objektum referencia: a másolatok ugyanazt a példányt látják és módosítják.
+    Array(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<OrderedMap>>),
+    Object(Rc<RefCell<Object>>),
+    Func(Rc<Closure>),
+    /// Értékként használt beépített függvény (`map(xs, len)`).
+    Builtin(Builtin),
     Void,
 }
 
+/// Függvényérték: a függvény indexe és a befogott változók közös cellái.
+pub struct Closure { pub func: usize, pub name: String, pub upvalues: Vec<Rc<RefCell<Value>>> }
+
+/// Egy lokális változó helye; a closure által befogott slot a closure-rel közös cellába kerül.
+#[derive(Clone)]
+enum Slot { Plain(Value), Shared(Rc<RefCell<Value>>) }
+
+impl Slot {
+    fn get(&self) -> Value { match self { Slot::Plain(v) => v.clone(), Slot::Shared(c) => c.borrow().clone() } }
+    fn set(&mut self, v: Value) { match self { Slot::Shared(c) => *c.borrow_mut() = v, s => *s = Slot::Plain(v) } }
+    fn share(&mut self) -> Rc<RefCell<Value>> {
+        match self {
+            Slot::Shared(c) => c.clone(),
+            Slot::Plain(v) => { let c = Rc::new(RefCell::new(std::mem::replace(v, Value::Void))); *self = Slot::Shared(c.clone()); c }
+        }
+    }
+}
+
+/// Map-kulcs: Int vagy Str érték.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Int(i64),
+    Str(String),
+    #[cfg(feature = "bigint")]
+    Big(num_bigint::BigInt),
+}
+
+impl Key {
+    fn from_value(v: &Value) -> std::result::Result<Key, RuntimeError> {
+        match v {
+            Value::Int(n) => Ok(Key::Int(*n)),
+            Value::Str(s) => Ok(Key::Str(s.clone())),
+            #[cfg(feature = "bigint")]
+            Value::BigInt(n) => Ok(Key::Big(n.clone())),
+            other => Err(RuntimeError::BadMapKey(other.type_name().to_string())),
+        }
+    }
+    fn to_value(&self) -> Value {
+        match self {
+            Key::Int(n) => Value::Int(*n),
+            Key::Str(s) => Value::Str(s.clone()),
+            #[cfg(feature = "bigint")]
+            Key::Big(n) => Value::BigInt(n.clone()),
+        }
+    }
+    /// Hibaüzenetekhez: a Str kulcs idézőjelek között.
+    fn describe(&self) -> String {
+        match self {
+            Key::Str(s) => format!("{:?}", s),
+            other => val_to_string(&other.to_value()),
+        }
+    }
+}
+
+/// Beszúrási sorrendet megőrző map: a bejárás és a kiírás sorrendje az első beszúrásé.
+#[derive(Clone, Default)]
+pub struct OrderedMap { entries: Vec<(Key, Value)>, index: HashMap<Key, usize> }
+
+impl OrderedMap {
+    pub fn len(&self) -> usize { self.entries.len() }
+    pub fn get(&self, k: &Key) -> Option<&Value> { self.index.get(k).map(|&i| &self.entries[i].1) }
+    pub fn contains(&self, k: &Key) -> bool { self.index.contains_key(k) }
+    /// Meglévő kulcsnál az érték cserélődik, a helye marad.
+    pub fn insert(&mut self, k: Key, v: Value) {
+        match self.index.get(&k) {
+            Some(&i) => self.entries[i].1 = v,
+            None => { self.index.insert(k.clone(), self.entries.len()); self.entries.push((k, v)); }
+        }
+    }
+    pub fn remove(&mut self, k: &Key) -> Option<Value> {
+        let i = self.index.remove(k)?;
+        let (_, v) = self.entries.remove(i);
+        for (j, (k, _)) in self.entries.iter().enumerate().skip(i) { self.index.insert(k.clone(), j); }
+        Some(v)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (&Key, &Value)> { self.entries.iter().map(|(k, v)| (k, v)) }
+}
+
+/// Egy osztály példánya; `fields` a `class.fields` sorrendjében.
+pub struct Object { pub class: Rc<ClassIR>, pub fields: Vec<Value> }
+
+/// Egy nyitott `try` blokk: a kezelő címe és a verem mélysége a blokk elején.
+struct Handler { target: usize, depth: usize }
+
+/// Egy hívási keret. A futó keret a `call` ciklusában él, a hívói a `frames`-ben várnak, így
+/// a szkripthívás nem fogyaszt Rust-vermet; a várakozó keretek a gyűjtő gyökerei.
+struct Frame { func: usize, ip: usize, stack: Vec<Value>, locals: Vec<Slot>, handlers: Vec<Handler>, upvalues: Vec<Rc<RefCell<Value>>>, ret: Ret }
+
+/// Mi történik a keret visszatérési értékével a hívóban.
+enum Ret {
+    Push,
+    /// a mezők kezdőértékadása után: a konstruktor hívása a hívó vermén `start`-tól álló argumentumokkal
+    Init { class: Rc<ClassIR>, obj: Value, start: usize, span: Span },
+    /// a konstruktor eredménye helyett az új objektum
+    Ctor(Value),
+    /// `map`/`filter` `i`-edik visszahívása; a függvény, a pillanatkép és az eredmény a hívó
+    /// vermének tetején áll (gyökerek)
+    Hof { b: Builtin, i: usize, span: Span },
+}
+
+/// Az `exec` kilépési oka: új keret indul, vagy a futó keret visszatér.
+enum Step { Call(Frame), Return(Value) }
+
+/// Az egymásba ágyazott szkripthívások felső korlátja (a keretek a heapen vannak).
+const MAX_CALL_DEPTH: usize = 100_000;
+
 pub struct VM {
     funcs: Vec<FunctionIR>,
+    classes: Vec<Rc<ClassIR>>,
     index: HashMap<String, usize>,
+    /// A konstruktorok indexei: ezek nem hívhatók metódusként, csak `super`-rel konstruktorból,
+    /// mert beállíthatják a `let` mezőket.
+    ctors: HashSet<usize>,
+    /// `None`: a globális kezdőértékadása még nem futott le.
+    globals: RefCell<Vec<Option<Value>>>,
+    global_names: Vec<String>,
+    init: usize,
+    heap: RefCell<Heap>,
+    /// A hívó keretek verme és lokálisai a hívás idejére ide kerülnek, hogy a gyűjtő lássa őket.
+    frames: RefCell<Vec<Frame>>,
+    /// Az élő szkriptkeretek száma (`MAX_CALL_DEPTH`).
+    depth: Cell<usize>,
+    /// A `catch`-nek átadott hibaüzenetek nyelve.
+    lp: Langpack,
+}
+
+impl Value {
+    pub fn array(items: Vec<Value>) -> Value { Value::Array(Rc::new(RefCell::new(items))) }
+    pub fn map(m: OrderedMap) -> Value { Value::Map(Rc::new(RefCell::new(m))) }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "Int", Value::Float(_) => "Float", Value::Str(_) => "Str", Value::Bool(_) => "Bool", Value::Array(_) => "Array", Value::Map(_) => "Map", Value::Object(_) => "Object", Value::Func(_) | Value::Builtin(_) => "Func", Value::Void => "Void",
+            // a nyelv szempontjából ugyanaz az egész típus
+            #[cfg(feature = "bigint")]
+            Value::BigInt(_) => "Int",
+        }
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(n) => f.debug_tuple("Int").field(n).finish(),
+            Value::Float(x) => f.debug_tuple("Float").field(x).finish(),
+            #[cfg(feature = "bigint")]
+            Value::BigInt(n) => write!(f, "BigInt({})", n),
+            Value::Str(s) => f.debug_tuple("Str").field(s).finish(),
+            Value::Bool(b) => f.debug_tuple("Bool").field(b).finish(),
+            Value::Array(a) => f.debug_tuple("Array").field(&a.borrow()).finish(),
+            Value::Map(m) => {
+                f.write_str("Map(")?;
+                f.debug_map().entries(m.borrow().iter().map(|(k, v)| (k.to_value(), v))).finish()?;
+                f.write_str(")")
+            }
+            Value::Object(o) => {
+                let o = o.borrow();
+                let mut s = f.debug_struct(&o.class.name);
+                for (name, v) in o.class.fields.iter().zip(&o.fields) { s.field(name, v); }
+                s.finish()
+            }
+            Value::Func(c) => f.debug_tuple("Func").field(&c.name).finish(),
+            Value::Builtin(b) => f.debug_tuple("Func").field(&b.name()).finish(),
+            Value::Void => f.write_str("Void"),
+        }
+    }
 }
 
 impl VM {
     pub fn new(p: ProgramIR) -> Self {
         let mut index = HashMap::new();
         for (i, f) in p.functions.iter().enumerate() { index.insert(f.name.clone(), i); }
-        Self { funcs: p.functions, index }
+        let globals = RefCell::new(vec![None; p.globals.len()]);
+        let ctors = p.classes.iter().filter_map(|c| c.ctor).collect();
+        Self {
+            funcs: p.functions, classes: p.classes.into_iter().map(Rc::new).collect(), index, ctors, globals, global_names: p.globals, init: p.init,
+            heap: RefCell::new(Heap::new()), frames: RefCell::new(Vec::new()), depth: Cell::new(0), lp: Langpack::default(),
+        }
     }
 
+    /// `--gc-stress`: gyűjtés minden foglalás után, a hiányzó gyökerek felderítéséhez.
+    pub fn set_gc_stress(&mut self, on: bool) { self.heap.get_mut().stress = on; }
+
+    pub fn set_langpack(&mut self, lp: Langpack) { self.lp = lp; }
+
+    pub fn has_function(&self, name: &str) -> bool { self.index.contains_key(name) }
+
     pub fn run(&self, entry: &str) -> Result<Value> {
-        let idx = *self.index.get(entry).ok_or_else(|| anyhow!(format!("Nincs ilyen függvény: {}", entry)))?;
+        let idx = *self.index.get(entry).ok_or_else(|| RunaError::Runtime { kind: RuntimeError::UnknownFunction(entry.to_string()), span: None })?;
+        self.call(self.init, Vec::new())?;
         self.call(idx, Vec::new())
     }
 
+    /// A belépési pont hívása; a további szkripthívások (a `map`/`filter` visszahívásai is)
+    /// ugyanebben a ciklusban futnak.
     fn call(&self, idx: usize, args: Vec<Value>) -> Result<Value> {
+        let base = self.frames.borrow().len();
+        let mut cur = self.frame(idx, args, Vec::new(), Ret::Push, None)?;
+        loop {
+            let step = match self.exec(&mut cur) {
+                Ok(Step::Call(callee)) => { self.frames.borrow_mut().push(std::mem::replace(&mut cur, callee)); Ok(()) }
+                Ok(Step::Return(v)) => {
+                    self.depth.set(self.depth.get() - 1);
+                    if self.frames.borrow().len() == base { return Ok(v); }
+                    let done = std::mem::replace(&mut cur, self.frames.borrow_mut().pop().expect("caller frame"));
+                    self.resume(&mut cur, done.ret, v)
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = step { self.unwind(&mut cur, e, base)?; }
+        }
+    }
+
+    /// Új keret `idx` hívásához; az aritás- és mélységhiba a hívás helyére (`span`) kerül.
+    fn frame(&self, idx: usize, args: Vec<Value>, upvalues: Vec<Rc<RefCell<Value>>>, ret: Ret, span: Option<Span>) -> Result<Frame> {
         let f = &self.funcs[idx];
-        if args.len() != f.arity { return Err(anyhow!("Arity mismatch")); }
-        let mut stack: Vec<Value> = Vec::new();
-        let mut locals: Vec<Value> = vec![Value::Void; f.local_count.max(args.len())];
-        for i in 0..args.len() { locals[i] = args[i].clone(); }
-        let mut ip: usize = 0;
+        if args.len() != f.arity {
+            // a rejtett `self` nem számít bele a felhasználónak jelzett paraméterszámba
+            let hidden = f.method as usize;
+            return Err(RunaError::Runtime { kind: RuntimeError::ArityMismatch { name: f.name.clone(), expected: f.arity - hidden, got: args.len() - hidden }, span });
+        }
+        if self.depth.get() >= MAX_CALL_DEPTH { return Err(RunaError::Runtime { kind: RuntimeError::CallDepth(MAX_CALL_DEPTH), span }); }
+        self.depth.set(self.depth.get() + 1);
+        let mut locals: Vec<Slot> = args.into_iter().map(Slot::Plain).collect();
+        locals.resize(f.local_count.max(locals.len()), Slot::Plain(Value::Void));
+        Ok(Frame { func: idx, ip: 0, stack: Vec::new(), locals, handlers: Vec::new(), upvalues, ret })
+    }
+
+    /// Függvényérték hívása: szkriptfüggvénynél az új keret, beépítettnél (keret nélkül) az eredmény.
+    fn callee(&self, callee: &Value, args: Vec<Value>, ret: Ret, stack: &mut Vec<Value>, span: Span) -> Result<Step> {
+        match callee {
+            Value::Func(c) => self.frame(c.func, args, c.upvalues.clone(), ret, Some(span)).map(Step::Call),
+            // `map`/`filter` értékként is a hívó vermén tartja a gyökereit; visszahívásként
+            // (`Ret::Hof`) egy argumentumot kapna, az aritáshiba előbb jön
+            Value::Builtin(b) => self.builtin(*b, args, stack, span),
+            other => Err(RunaError::Runtime { kind: RuntimeError::NotCallable(other.type_name().to_string()), span: Some(span) }),
+        }
+    }
+
+    /// A visszatért keret értékének átadása a hívónak (`cur`); konstruktorhívásnál a következő lépés.
+    fn resume(&self, cur: &mut Frame, ret: Ret, v: Value) -> Result<()> {
+        match ret {
+            Ret::Push => cur.stack.push(v),
+            Ret::Ctor(obj) => {
+                cur.stack.push(obj);
+                self.safepoint(&cur.stack, &cur.locals, &cur.upvalues);
+            }
+            Ret::Hof { b, i, span } => {
+                hof_collect(b, i, v, &cur.stack, span)?;
+                match self.hof_next(b, i + 1, &mut cur.stack, span)? {
+                    Step::Call(callee) => self.frames.borrow_mut().push(std::mem::replace(cur, callee)),
+                    Step::Return(out) => cur.stack.push(out),
+                }
+            }
+            // a konstruktor argumentumai a mezők kezdőértékadása alatt a veremben maradtak (gyökerek)
+            Ret::Init { class, obj, start, span } => {
+                let mut call_args = cur.stack.split_off(start);
+                match class.ctor {
+                    Some(ctor) if self.funcs[ctor].arity != call_args.len() + 1 => {
+                        return Err(RunaError::Runtime { kind: RuntimeError::ArityMismatch { name: class.name.clone(), expected: self.funcs[ctor].arity - 1, got: call_args.len() }, span: Some(span) });
+                    }
+                    Some(ctor) => {
+                        call_args.insert(0, obj.clone());
+                        let callee = self.frame(ctor, call_args, Vec::new(), Ret::Ctor(obj), Some(span))?;
+                        self.frames.borrow_mut().push(std::mem::replace(cur, callee));
+                    }
+                    None if !call_args.is_empty() => {
+                        return Err(RunaError::Runtime { kind: RuntimeError::ArityMismatch { name: class.name.clone(), expected: 0, got: call_args.len() }, span: Some(span) });
+                    }
+                    None => {
+                        cur.stack.push(obj);
+                        self.safepoint(&cur.stack, &cur.locals, &cur.upvalues);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
 
+    /// Futásidejű hiba: a legbelső nyitott `try` kezelője folytatja, a nélküle maradt keretek
+    /// lebomlanak; ha a `call` saját keretei közt nincs kezelő, a hiba a hívóhoz megy.
+    fn unwind(&self, cur: &mut Frame, e: RunaError, base: usize) -> Result<()> {
+        loop {
+            if let Some(h) = cur.handlers.pop().filter(|_| matches!(e, RunaError::Runtime { .. })) {
+                cur.stack.truncate(h.depth);
+                cur.stack.push(self.error_value(&e));
+                cur.ip = h.target;
+                return Ok(());
+            }
+            self.depth.set(self.depth.get() - 1);
+            if self.frames.borrow().len() == base { return Err(e); }
+            *cur = self.frames.borrow_mut().pop().expect("caller frame");
+        }
+    }
+
+    /// A keret futtatása a következő hívásig vagy a visszatérésig; a hibát a `call` adja át a
+    /// kezelőnek vagy a hívónak.
+    fn exec(&self, fr: &mut Frame) -> Result<Step> {
+        let f = &self.funcs[fr.func];
+        let mut ip = fr.ip;
+        let Frame { func, ip: resume_at, stack, locals, handlers, upvalues, .. } = fr;
+        let upvalues: &[Rc<RefCell<Value>>] = upvalues;
         while ip < f.chunk.code.len() {
+            let span = f.chunk.spans[ip];
+            let err = |kind| RunaError::Runtime { kind, span: Some(span) };
             match &f.chunk.code[ip] {
                 Op::PushInt(n) => stack.push(Value::Int(*n)),
+                Op::PushFloat(x) => stack.push(Value::Float(*x)),
                 Op::PushStr(s) => stack.push(Value::Str(s.clone())),
                 Op::PushBool(b) => stack.push(Value::Bool(*b)),
                 Op::PushVoid => stack.push(Value::Void),
-                Op::LoadLocal(i) => stack.push(locals[*i].clone()),
+                Op::LoadLocal(i) => stack.push(locals[*i].get()),
                 Op::StoreLocal(i) => {
-                    let v = stack.pop().ok_or_else(|| anyhow!("Stack underflow"))?;
-                    if *i >= locals.len() { locals.resize(i+1, Value::Void); }
-                    locals[*i] = v;
+                    let v = stack.pop().ok_or_else(|| err(RuntimeError::StackUnderflow))?;
+                    if *i >= locals.len() { locals.resize(i+1, Slot::Plain(Value::Void)); }
+                    locals[*i].set(v);
+                }
+                Op::InitLocal(i) => {
+                    let v = stack.pop().ok_or_else(|| err(RuntimeError::StackUnderflow))?;
+                    if *i >= locals.len() { locals.resize(i+1, Slot::Plain(Value::Void)); }
+                    locals[*i] = Slot::Plain(v);
+                }
+                Op::LoadUpvalue(i) => stack.push(upvalues[*i].borrow().clone()),
+                Op::StoreUpvalue(i) => {
+                    let v = stack.pop().ok_or_else(|| err(RuntimeError::StackUnderflow))?;
+                    *upvalues[*i].borrow_mut() = v;
+                }
+                Op::MakeClosure(name, captures) => {
+                    let Some(&func) = self.index.get(name) else { return Err(err(RuntimeError::UnknownFunction(name.clone()))); };
+                    let cells: Vec<_> = captures.iter().map(|c| match *c {
+                        Capture::Local(i) => locals[i].share(),
+                        Capture::Upvalue(i) => upvalues[i].clone(),
+                    }).collect();
+                    for c in &cells { self.heap.borrow_mut().track_cell(c); }
+                    stack.push(Value::Func(Rc::new(Closure { func, name: name.clone(), upvalues: cells })));
+                    self.safepoint(stack, locals, upvalues);
+                }
+                Op::CallValue(argc) => {
+                    let start = stack.len().checked_sub(*argc + 1).ok_or_else(|| err(RuntimeError::StackUnderflow))?;
+                    let mut call_args = stack.split_off(start);
+                    let callee = call_args.remove(0);
+                    match self.callee(&callee, call_args, Ret::Push, stack, span)? {
+                        Step::Call(callee) => { *resume_at = ip + 1; return Ok(Step::Call(callee)); }
+                        Step::Return(v) => { stack.push(v); self.safepoint(stack, locals, upvalues); }
+                    }
+                }
+                Op::LoadGlobal(g) => {
+                    let v = self.globals.borrow()[*g].clone().ok_or_else(|| err(RuntimeError::UninitializedGlobal(self.global_names[*g].clone())))?;
+                    stack.push(v);
+                }
+                Op::StoreGlobal(g) => {
+                    let v = stack.pop().ok_or_else(|| err(RuntimeError::StackUnderflow))?;
+                    self.globals.borrow_mut()[*g] = Some(v);
                 }
                 Op::MakeArray(n) => {
-                    if stack.len() < *n { return Err(anyhow!("Stack underflow (MakeArray)")); }
+                    if stack.len() < *n { return Err(err(RuntimeError::StackUnderflow)); }
                     let start = stack.len() - *n;
-                    let mut v = stack.split_off(start);
-                    let arr = Value::Array(v.drain(..).collect());
+                    let arr = Value::array(stack.split_off(start));
+                    self.heap.borrow_mut().track(&arr);
                     stack.push(arr);
+                    self.safepoint(stack, locals, upvalues);
                 }
+                Op::MakeMap(n) => {
+                    if stack.len() < 2 * *n { return Err(err(RuntimeError::StackUnderflow)); }
+                    let start = stack.len() - 2 * *n;
+                    let mut map = OrderedMap::default();
+                    let mut items = stack.drain(start..);
+                    while let (Some(k), Some(v)) = (items.next(), items.next()) { map.insert(Key::from_value(&k).map_err(err)?, v); }
+                    drop(items);
+                    let map = Value::map(map);
+                    self.heap.borrow_mut().track(&map);
+                    stack.push(map);
+                    self.safepoint(stack, locals, upvalues);
+                }
+                Op::Iter => match stack.last() {
+                    // a kulcsok pillanatképe: a ciklustörzs módosíthatja a map-et
+                    Some(Value::Map(m)) => {
+                        let keys = Value::array(m.borrow().iter().map(|(k, _)| k.to_value()).collect());
+                        self.heap.borrow_mut().track(&keys);
+                        *stack.last_mut().unwrap() = keys;
+                        self.safepoint(stack, locals, upvalues);
+                    }
+                    Some(Value::Array(_) | Value::Str(_)) => {}
+                    Some(other) => return Err(err(RuntimeError::NotIterable(other.type_name().to_string()))),
+                    None => return Err(err(RuntimeError::StackUnderflow)),
+                },
                 Op::IndexGet => {
-                    let idx_v = stack.pop().ok_or_else(|| anyhow!("Stack underflow"))?;
-                    let tgt_v = stack.pop().ok_or_else(|| anyhow!("Stack underflow"))?;
-                    let i = match idx_v { Value::Int(k) => k as usize, _ => return Err(anyhow!("Index nem egész")) };
-                    match tgt_v {
-                        Value::Array(a) => {
-                            if i >= a.len() { return Err(anyhow!("Index tartományon kívül")); }
-                            stack.push(a[i].clone());
+                    let idx_v = stack.pop().ok_or_else(|| err(RuntimeError::StackUnderflow))?;
+                    let tgt_v = stack.pop().ok_or_else(|| err(RuntimeError::StackUnderflow))?;
+                    match (&tgt_v, &idx_v) {
+                        (Value::Array(a), Value::Int(k)) => {
+                            let a = a.borrow();
+                            if *k < 0 || *k as usize >= a.len() { return Err(err(RuntimeError::IndexOutOfRange { index: *k, len: a.len() })); }
+                            stack.push(a[*k as usize].clone());
+                        }
+                        // Unicode karakter (scalar) szerinti index
+                        (Value::Str(s), Value::Int(k)) => {
+                            let c = usize::try_from(*k).ok().and_then(|i| s.chars().nth(i));
+                            let c = c.ok_or_else(|| err(RuntimeError::IndexOutOfRange { index: *k, len: s.chars().count() }))?;
+                            stack.push(Value::Str(c.to_string()));
+                        }
+                        (Value::Map(m), _) => {
+                            let k = Key::from_value(&idx_v).map_err(err)?;
+                            let v = m.borrow().get(&k).cloned().ok_or_else(|| err(RuntimeError::KeyNotFound(k.describe())))?;
+                            stack.push(v);
                         }
-                        _ => return Err(anyhow!("Indexelés csak tömbön támogatott")),
+                        _ => return Err(err(RuntimeError::TypeMismatch { op: "[]".to_string(), left: tgt_v.type_name().to_string(), right: idx_v.type_name().to_string() })),
                     }
                 }
-                Op::Add | Op::Sub | Op::Mul | Op::Div |
+                Op::Slice => {
+                    let end = stack.pop().ok_or_else(|| err(RuntimeError::StackUnderflow))?;
+                    let start = stack.pop().ok_or_else(|| err(RuntimeError::StackUnderflow))?;
+                    let tgt_v = stack.pop().ok_or_else(|| err(RuntimeError::StackUnderflow))?;
+                    let len = match &tgt_v { Value::Array(a) => a.borrow().len(), Value::Str(s) => s.chars().count(), _ => 0 };
+                    // a határok a [0, hossz] tartományba szorulnak; kezdet >= vég: üres szelet
+                    let bound = |v: &Value, default: usize| match v {
+                        Value::Void => Ok(default),
+                        Value::Int(n) => Ok((*n).clamp(0, len as i64) as usize),
+                        other => Err(err(RuntimeError::TypeMismatch { op: "[:]".to_string(), left: tgt_v.type_name().to_string(), right: other.type_name().to_string() })),
+                    };
+                    let (i, j) = (bound(&start, 0)?, bound(&end, len)?);
+                    let j = j.max(i);
+                    let v = match &tgt_v {
+                        Value::Array(a) => Value::array(a.borrow()[i..j].to_vec()),
+                        Value::Str(s) => Value::Str(s.chars().skip(i).take(j - i).collect()),
+                        _ => return Err(err(RuntimeError::TypeMismatch { op: "[:]".to_string(), left: tgt_v.type_name().to_string(), right: start.type_name().to_string() })),
+                    };
+                    self.heap.borrow_mut().track(&v);
+                    stack.push(v);
+                    self.safepoint(stack, locals, upvalues);
+                }
+                Op::IndexSet => {
+                    let val = stack.pop().ok_or_else(|| err(RuntimeError::StackUnderflow))?;
+                    let idx_v = stack.pop().ok_or_else(|| err(RuntimeError::StackUnderflow))?;
+                    let tgt_v = stack.pop().ok_or_else(|| err(RuntimeError::StackUnderflow))?;
+                    match (tgt_v, &idx_v) {
+                        (Value::Array(a), Value::Int(k)) => {
+                            let mut a = a.borrow_mut();
+                            if *k < 0 || *k as usize >= a.len() { return Err(err(RuntimeError::IndexOutOfRange { index: *k, len: a.len() })); }
+                            a[*k as usize] = val;
+                        }
+                        (Value::Map(m), _) => m.borrow_mut().insert(Key::from_value(&idx_v).map_err(err)?, val),
+                        (tgt_v, _) => return Err(err(RuntimeError::TypeMismatch { op: "[]=".to_string(), left: tgt_v.type_name().to_string(), right: idx_v.type_name().to_string() })),
+                    }
+                }
+                Op::Dup => {
+                    let v = stack.last().cloned().ok_or_else(|| err(RuntimeError::StackUnderflow))?;
+                    stack.push(v);
+                }
+                Op::GetField(name) => {
+                    let obj = stack.pop().ok_or_else(|| err(RuntimeError::StackUnderflow))?;
+                    let o = as_object(&obj, name).map_err(err)?;
+                    let o = o.borrow();
+                    let slot = field_slot(&o, name).map_err(err)?;
+                    stack.push(o.fields[slot].clone());
+                }
+                Op::SetField(name) | Op::InitField(name) => {
+                    let val = stack.pop().ok_or_else(|| err(RuntimeError::StackUnderflow))?;
+                    let obj = stack.pop().ok_or_else(|| err(RuntimeError::StackUnderflow))?;
+                    {
+                        let mut o = as_object(&obj, name).map_err(err)?.borrow_mut();
+                        let slot = field_slot(&o, name).map_err(err)?;
+                        if !o.class.mutable[slot] && matches!(f.chunk.code[ip], Op::SetField(_)) {
+                            return Err(err(RuntimeError::AssignImmutableField { class: o.class.name.clone(), name: name.clone() }));
+                        }
+                        o.fields[slot] = val;
+                    }
+                }
+                Op::New(ci, argc) => {
+                    let class = self.classes[*ci].clone();
+                    let start = stack.len().checked_sub(*argc).ok_or_else(|| err(RuntimeError::StackUnderflow))?;
+                    let obj = Value::Object(Rc::new(RefCell::new(Object { class: class.clone(), fields: vec![Value::Void; class.fields.len()] })));
+                    self.heap.borrow_mut().track(&obj);
+                    // előbb a mezők kezdőértékei, a visszatérés után a konstruktor (`Ret::Init`)
+                    let callee = self.frame(class.init, vec![obj.clone()], Vec::new(), Ret::Init { class: class.clone(), obj, start, span }, Some(span))?;
+                    *resume_at = ip + 1;
+                    return Ok(Step::Call(callee));
+                }
+                Op::CallMethod(name, argc) => {
+                    let start = stack.len().checked_sub(*argc + 1).ok_or_else(|| err(RuntimeError::StackUnderflow))?;
+                    let call_args = stack.split_off(start);
+                    let obj = as_object(&call_args[0], name).map_err(err)?.clone();
+                    let class = obj.borrow().class.clone();
+                    let step = match class.methods.get(name).filter(|m| !self.ctors.contains(m)) {
+                        Some(&method) => Step::Call(self.frame(method, call_args, Vec::new(), Ret::Push, Some(span))?),
+                        // metódus híján a mezőben tárolt függvényérték
+                        None => {
+                            let field = { let o = obj.borrow(); field_slot(&o, name).ok().map(|slot| o.fields[slot].clone()) };
+                            let Some(field) = field else { return Err(err(RuntimeError::NoSuchMethod { class: class.name.clone(), name: name.clone() })) };
+                            self.callee(&field, call_args[1..].to_vec(), Ret::Push, stack, span)?
+                        }
+                    };
+                    match step {
+                        Step::Call(callee) => { *resume_at = ip + 1; return Ok(Step::Call(callee)); }
+                        Step::Return(v) => { stack.push(v); self.safepoint(stack, locals, upvalues); }
+                    }
+                }
+                Op::CallSuper(ci, name, argc) => {
+                    let start = stack.len().checked_sub(*argc + 1).ok_or_else(|| err(RuntimeError::StackUnderflow))?;
+                    let call_args = stack.split_off(start);
+                    let class = &self.classes[*ci];
+                    let method = *class.methods.get(name).filter(|m| !self.ctors.contains(m) || self.ctors.contains(func))
+                        .ok_or_else(|| err(RuntimeError::NoSuchMethod { class: class.name.clone(), name: name.clone() }))?;
+                    let callee = self.frame(method, call_args, Vec::new(), Ret::Push, Some(span))?;
+                    *resume_at = ip + 1;
+                    return Ok(Step::Call(callee));
+                }
+                Op::Dup2 => {
+                    let n = stack.len();
+                    if n < 2 { return Err(err(RuntimeError::StackUnderflow)); }
+                    stack.extend_from_within(n - 2..);
+                }
+                Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Mod |
                 Op::Eq | Op::Ne | Op::Lt | Op::Le | Op::Gt | Op::Ge => {
-                    let b = stack.pop().ok_or_else(|| anyhow!("Stack underflow"))?;
-                    let a = stack.pop().ok_or_else(|| anyhow!("Stack underflow"))?;
-                    stack.push(apply_binop(&a, &b, &f.chunk.code[ip])?);
+                    let b = stack.pop().ok_or_else(|| err(RuntimeError::StackUnderflow))?;
+                    let a = stack.pop().ok_or_else(|| err(RuntimeError::StackUnderflow))?;
+                    stack.push(apply_binop(&a, &b, &f.chunk.code[ip]).map_err(err)?);
                 }
+                Op::Neg | Op::Not => {
+                    let v = stack.pop().ok_or_else(|| err(RuntimeError::StackUnderflow))?;
+                    stack.push(apply_unop(&v, &f.chunk.code[ip]).map_err(err)?);
+                }
+                Op::CallBuiltin(b, argc) => {
+                    let start = stack.len().checked_sub(*argc).ok_or_else(|| err(RuntimeError::StackUnderflow))?;
+                    let args = stack.split_off(start);
+                    match self.builtin(*b, args, stack, span)? {
+                        Step::Call(callee) => { *resume_at = ip + 1; return Ok(Step::Call(callee)); }
+                        Step::Return(v) => { stack.push(v); self.safepoint(stack, locals, upvalues); }
+                    }
+                }
+                Op::PushBuiltin(b) => stack.push(Value::Builtin(*b)),
                 Op::CallName(name, argc) => {
-    if name == "kiir" || name == "print" {
-        let mut args_buf = Vec::new();
-        for _ in 0..*argc { args_buf.push(stack.pop().unwrap()); }
-        args_buf.reverse();
-        let line = args_buf.iter().map(val_to_string).collect::<Vec<_>>().join(" ");
-        println!("{}", line);
-        stack.push(Value::Void);
-
-    } else if name == "len" {
-        if *argc != 1 { return Err(anyhow!("len: 1 paraméter kell")); }
-        let v = stack.pop().unwrap();
-        let n = match v {
-            Value::Array(a) => a.len() as i64,
-            Value::Str(s) => s.chars().count() as i64,
-            _ => return Err(anyhow!("len: csak tömb vagy string")),
-        };
-        stack.push(Value::Int(n));
-
-    } else if name == "push" {
-        if *argc != 2 { return Err(anyhow!("push: 2 paraméter kell")); }
-        let v = stack.pop().unwrap();
-        let arr = stack.pop().unwrap();
-        match arr {
-            Value::Array(mut a) => { a.push(v); stack.push(Value::Array(a)); }
-            _ => return Err(anyhow!("push: első paraméter tömb legyen")),
-        }
-
-    } else if let Some(&callee_idx) = self.index.get(name) {
-        let mut call_args = Vec::new();
-        for _ in 0..*argc { call_args.push(stack.pop().unwrap()); }
-        call_args.reverse();
-        let ret = self.call(callee_idx, call_args)?;
-        stack.push(ret);
-
-    } else {
-        return Err(anyhow!(format!("Ismeretlen függvény: {}", name)));
-    }
-}
+                    let Some(&callee_idx) = self.index.get(name) else { return Err(err(RuntimeError::UnknownFunction(name.clone()))); };
+                    let start = stack.len().checked_sub(*argc).ok_or_else(|| err(RuntimeError::StackUnderflow))?;
+                    let call_args = stack.split_off(start);
+                    let callee = self.frame(callee_idx, call_args, Vec::new(), Ret::Push, Some(span))?;
+                    *resume_at = ip + 1;
+                    return Ok(Step::Call(callee));
+                }
 
                 Op::Pop => { stack.pop(); }
                 Op::Jump(tgt) => { ip = *tgt; continue; }
+                Op::TryBegin(tgt) => handlers.push(Handler { target: *tgt, depth: stack.len() }),
+                Op::TryEnd => { handlers.pop(); }
                 Op::JumpIfFalse(tgt) => {
-                    let v = stack.pop().ok_or_else(|| anyhow!("Stack underflow"))?;
+                    let v = stack.pop().ok_or_else(|| err(RuntimeError::StackUnderflow))?;
                     if matches!(v, Value::Bool(false)) { ip = *tgt; continue; }
                 }
-                Op::Return => {
-                    let v = stack.pop().unwrap_or(Value::Void);
-                    return Ok(v);
-                }
+                Op::CheckBool(op) => match stack.last() {
+                    Some(Value::Bool(_)) => {}
+                    Some(v) => return Err(err(RuntimeError::NotABool { op: op.to_string(), got: v.type_name().to_string() })),
+                    None => return Err(err(RuntimeError::StackUnderflow)),
+                },
+                Op::Return => return Ok(Step::Return(stack.pop().unwrap_or(Value::Void))),
             }
             ip += 1;
         }
-        Ok(Value::Void)
+        Ok(Step::Return(Value::Void))
+    }
+
+    /// Foglalás utáni biztonságos pont: minden élő érték a veremben, a lokálisokban, a befogott
+    /// cellákban, a felfüggesztett keretekben vagy a globálisokban van.
+    fn safepoint(&self, stack: &[Value], locals: &[Slot], upvalues: &[Rc<RefCell<Value>>]) {
+        if !self.heap.borrow().should_collect() { return; }
+        let mut marker = Marker::default();
+        let mut roots = |stack: &[Value], locals: &[Slot], upvalues: &[Rc<RefCell<Value>>]| {
+            for v in stack { marker.value(v); }
+            for s in locals { match s { Slot::Plain(v) => marker.value(v), Slot::Shared(c) => marker.cell(c) } }
+            for c in upvalues { marker.cell(c); }
+        };
+        roots(stack, locals, upvalues);
+        for f in self.frames.borrow().iter() { roots(&f.stack, &f.locals, &f.upvalues); }
+        for v in self.globals.borrow().iter().flatten() { marker.value(v); }
+        self.heap.borrow_mut().sweep(marker);
+    }
+
+    /// A `catch (e)` értéke: `{"code": "E04xx", "message": "..."}`.
+    fn error_value(&self, e: &RunaError) -> Value {
+        let mut m = OrderedMap::default();
+        m.insert(Key::Str("code".to_string()), Value::Str(e.code().to_string()));
+        m.insert(Key::Str("message".to_string()), Value::Str(e.message(&self.lp)));
+        let v = Value::map(m);
+        self.heap.borrow_mut().track(&v);
+        v
+    }
+
+    fn gc_stats(&self) -> Value {
+        let s = self.heap.borrow().stats;
+        let mut m = OrderedMap::default();
+        for (k, n) in [("collections", s.collections), ("allocated", s.allocated), ("freed", s.freed), ("live", s.live)] {
+            m.insert(Key::Str(k.to_string()), Value::Int(n as i64));
+        }
+        Value::map(m)
     }
+
+    /// Beépített függvény hívása. `map`/`filter` visszahívásához keretet ad (`Step::Call`), a
+    /// többi rögtön az eredményt.
+    fn builtin(&self, b: Builtin, args: Vec<Value>, stack: &mut Vec<Value>, span: Span) -> Result<Step> {
+        let err = |kind| RunaError::Runtime { kind, span: Some(span) };
+        let v = match b {
+            Builtin::Map | Builtin::Filter => match self.hof(b, args, stack, span)? {
+                Step::Return(out) => out,
+                call => return Ok(call),
+            },
+            Builtin::GcStats if !args.is_empty() => return Err(err(RuntimeError::ArityMismatch { name: b.name().to_string(), expected: 0, got: args.len() })),
+            Builtin::GcStats => self.gc_stats(),
+            _ => call_builtin(b, args).map_err(err)?,
+        };
+        self.heap.borrow_mut().track(&v);
+        Ok(Step::Return(v))
+    }
+
+    /// Függvényértéket hívó beépítettek: `map(tömb, f)`, `filter(tömb, f)`. Az első szkriptbeli
+    /// visszahívás keretét adja, a többit `resume` indítja (`Ret::Hof`).
+    fn hof(&self, b: Builtin, args: Vec<Value>, stack: &mut Vec<Value>, span: Span) -> Result<Step> {
+        let err = |kind| RunaError::Runtime { kind, span: Some(span) };
+        if args.len() != 2 { return Err(err(RuntimeError::ArityMismatch { name: b.name().to_string(), expected: 2, got: args.len() })); }
+        let mut it = args.into_iter();
+        let (items, f) = (it.next().unwrap(), it.next().unwrap());
+        let Value::Array(items) = items else {
+            return Err(err(RuntimeError::BadArgument { func: b.name().to_string(), expected: "Array".to_string(), got: items.type_name().to_string() }));
+        };
+        // pillanatkép: a függvény módosíthatja a bejárt tömböt
+        let snapshot = items.borrow().clone();
+        let out = Value::Array(Rc::new(RefCell::new(Vec::new())));
+        if snapshot.is_empty() { return Ok(Step::Return(out)); }
+        self.heap.borrow_mut().track(&out);
+        // a függvény, a pillanatkép és az eredmény a visszahívások alatt a hívó vermén gyökér
+        stack.extend([f, Value::Array(Rc::new(RefCell::new(snapshot))), out]);
+        self.hof_next(b, 0, stack, span)
+    }
+
+    /// A `map`/`filter` visszahívásai az `i`-edik elemtől: az első szkriptfüggvény kerete, vagy
+    /// ha a függvény beépített és minden elem elfogyott, a gyökerek helyett az eredmény.
+    fn hof_next(&self, b: Builtin, mut i: usize, stack: &mut Vec<Value>, span: Span) -> Result<Step> {
+        let n = stack.len();
+        let (f, snapshot) = (stack[n - 3].clone(), stack[n - 2].clone());
+        let Value::Array(items) = &snapshot else { unreachable!("map/filter roots") };
+        loop {
+            let Some(x) = items.borrow().get(i).cloned() else {
+                let out = stack.pop().expect("map/filter result");
+                stack.truncate(n - 3);
+                return Ok(Step::Return(out));
+            };
+            match self.callee(&f, vec![x], Ret::Hof { b, i, span }, stack, span)? {
+                Step::Return(v) => hof_collect(b, i, v, stack, span)?,
+                call => return Ok(call),
+            }
+            i += 1;
+        }
+    }
+}
+
+/// A `map`/`filter` `i`-edik visszahívásának eredménye a hívó vermének tetején álló eredménytömbbe.
+fn hof_collect(b: Builtin, i: usize, v: Value, stack: &[Value], span: Span) -> Result<()> {
+    let n = stack.len();
+    let (Value::Array(snapshot), Value::Array(out)) = (&stack[n - 2], &stack[n - 1]) else { unreachable!("map/filter roots") };
+    match (b, v) {
+        (Builtin::Map, v) => out.borrow_mut().push(v),
+        (_, Value::Bool(keep)) => if keep { let x = snapshot.borrow()[i].clone(); out.borrow_mut().push(x) },
+        (_, other) => return Err(RunaError::Runtime { kind: RuntimeError::BadArgument { func: b.name().to_string(), expected: "Bool".to_string(), got: other.type_name().to_string() }, span: Some(span) }),
+    }
+    Ok(())
+}
+
+fn as_object<'v>(v: &'v Value, member: &str) -> std::result::Result<&'v Rc<RefCell<Object>>, RuntimeError> {
+    match v {
+        Value::Object(o) => Ok(o),
+        other => Err(RuntimeError::NotAnObject { name: member.to_string(), got: other.type_name().to_string() }),
+    }
+}
+
+fn field_slot(o: &Object, name: &str) -> std::result::Result<usize, RuntimeError> {
+    o.class.fields.iter().position(|f| f == name).ok_or_else(|| RuntimeError::NoSuchField { class: o.class.name.clone(), name: name.to_string() })
 }
 
-fn val_to_string(v: &Value) -> String {
+fn text(b: Builtin, v: &Value) -> std::result::Result<&str, RuntimeError> {
     match v {
+        Value::Str(s) => Ok(s),
+        other => Err(RuntimeError::BadArgument { func: b.name().to_string(), expected: "Str".to_string(), got: other.type_name().to_string() }),
+    }
+}
+
+fn call_builtin(b: Builtin, args: Vec<Value>) -> std::result::Result<Value, RuntimeError> {
+    let arity = |n: usize| if args.len() == n { Ok(()) } else { Err(RuntimeError::ArityMismatch { name: b.name().to_string(), expected: n, got: args.len() }) };
+    let bad = |expected: &str, got: &Value| RuntimeError::BadArgument { func: b.name().to_string(), expected: expected.to_string(), got: got.type_name().to_string() };
+    Ok(match b {
+        Builtin::Print => {
+            let line = args.iter().map(val_to_string).collect::<Vec<_>>().join(" ");
+            println!("{}", line);
+            Value::Void
+        }
+        Builtin::Len => {
+            arity(1)?;
+            match &args[0] {
+                Value::Array(a) => Value::Int(a.borrow().len() as i64),
+                Value::Map(m) => Value::Int(m.borrow().len() as i64),
+                Value::Str(s) => Value::Int(s.chars().count() as i64),
+                other => return Err(bad("Array/Map/Str", other)),
+            }
+        }
+        Builtin::Map | Builtin::Filter | Builtin::GcStats => unreachable!("handled by the VM"),
+        Builtin::Push => {
+            arity(2)?;
+            let mut it = args.into_iter();
+            match (it.next().unwrap(), it.next().unwrap()) {
+                // helyben bővít; a visszaadott érték ugyanaz a tömb
+                (Value::Array(a), v) => { a.borrow_mut().push(v); Value::Array(a) }
+                (other, _) => return Err(bad("Array", &other)),
+            }
+        }
+        Builtin::Keys | Builtin::Values => {
+            arity(1)?;
+            let Value::Map(m) = &args[0] else { return Err(bad("Map", &args[0])) };
+            Value::array(m.borrow().iter().map(|(k, v)| if b == Builtin::Keys { k.to_value() } else { v.clone() }).collect())
+        }
+        Builtin::Has => {
+            arity(2)?;
+            let Value::Map(m) = &args[0] else { return Err(bad("Map", &args[0])) };
+            Value::Bool(m.borrow().contains(&Key::from_value(&args[1])?))
+        }
+        Builtin::Split => {
+            arity(2)?;
+            let (s, sep) = (text(b, &args[0])?, text(b, &args[1])?);
+            // üres elválasztó: karakterekre bont
+            let parts: Vec<Value> = if sep.is_empty() { s.chars().map(|c| Value::Str(c.to_string())).collect() } else { s.split(sep).map(|p| Value::Str(p.to_string())).collect() };
+            Value::array(parts)
+        }
+        Builtin::Join => {
+            arity(2)?;
+            let Value::Array(a) = &args[0] else { return Err(bad("Array", &args[0])) };
+            Value::Str(a.borrow().iter().map(val_to_string).collect::<Vec<_>>().join(text(b, &args[1])?))
+        }
+        Builtin::Trim | Builtin::Upper | Builtin::Lower => {
+            arity(1)?;
+            let s = text(b, &args[0])?;
+            Value::Str(match b { Builtin::Trim => s.trim().to_string(), Builtin::Upper => s.to_uppercase(), _ => s.to_lowercase() })
+        }
+        Builtin::Contains | Builtin::StartsWith => {
+            arity(2)?;
+            let (s, sub) = (text(b, &args[0])?, text(b, &args[1])?);
+            Value::Bool(if b == Builtin::Contains { s.contains(sub) } else { s.starts_with(sub) })
+        }
+        Builtin::Replace => {
+            arity(3)?;
+            Value::Str(text(b, &args[0])?.replace(text(b, &args[1])?, text(b, &args[2])?))
+        }
+        Builtin::Find => {
+            arity(2)?;
+            let s = text(b, &args[0])?;
+            // karakterindex, mint az indexelésnél; -1, ha nincs benne
+            Value::Int(s.find(text(b, &args[1])?).map_or(-1, |at| s[..at].chars().count() as i64))
+        }
+        Builtin::Format => {
+            let Some((fmt, values)) = args.split_first() else { return Err(RuntimeError::ArityMismatch { name: b.name().to_string(), expected: 1, got: 0 }) };
+            format_str(text(b, fmt)?, values)?
+        }
+        Builtin::Remove => {
+            arity(2)?;
+            let mut it = args.into_iter();
+            match (it.next().unwrap(), it.next().unwrap()) {
+                // a hiányzó kulcs nem hiba; a map a kulcs nélkül
+                (Value::Map(m), k) => { m.borrow_mut().remove(&Key::from_value(&k)?); Value::Map(m) }
+                (other, _) => return Err(bad("Map", &other)),
+            }
+        }
+        Builtin::Int => {
+            arity(1)?;
+            let fail = || RuntimeError::BadConversion { value: val_to_string(&args[0]), target: "Int".to_string() };
+            match &args[0] {
+                Value::Int(n) => Value::Int(*n),
+                // csonkol a nulla felé; a tartományon kívüli és nem véges értékek hibák
+                Value::Float(x) if x.is_finite() && x.trunc() >= i64::MIN as f64 && x.trunc() < i64::MAX as f64 => Value::Int(*x as i64),
+                #[cfg(feature = "bigint")]
+                Value::Float(x) if x.is_finite() => big::norm(num_traits::FromPrimitive::from_f64(x.trunc()).ok_or_else(fail)?),
+                Value::Float(_) => return Err(fail()),
+                #[cfg(feature = "bigint")]
+                Value::Str(s) => big::norm(s.trim().parse().map_err(|_| fail())?),
+                #[cfg(not(feature = "bigint"))]
+                Value::Str(s) => Value::Int(s.trim().parse().map_err(|_| fail())?),
+                #[cfg(feature = "bigint")]
+                Value::BigInt(n) => Value::BigInt(n.clone()),
+                other => return Err(bad("Int/Float/Str", other)),
+            }
+        }
+        Builtin::Float => {
+            arity(1)?;
+            match &args[0] {
+                Value::Int(n) => Value::Float(*n as f64),
+                Value::Float(x) => Value::Float(*x),
+                #[cfg(feature = "bigint")]
+                Value::BigInt(_) => Value::Float(as_f64(&args[0])),
+                Value::Str(s) => Value::Float(s.trim().parse().map_err(|_| RuntimeError::BadConversion { value: s.clone(), target: "Float".to_string() })?),
+                other => return Err(bad("Int/Float/Str", other)),
+            }
+        }
+    })
+}
+
+/// `format("{} + {} = {}", a, b, c)`: a `{}` helyőrzők sorban az értékek szöveges alakjai;
+/// `{{` és `}}` a kapcsos zárójel maga.
+fn format_str(fmt: &str, values: &[Value]) -> std::result::Result<Value, RuntimeError> {
+    let mut out = String::new();
+    let mut next = 0;
+    let mut chars = fmt.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => { chars.next(); out.push(c); }
+            ('{', Some('}')) => {
+                chars.next();
+                if let Some(v) = values.get(next) { out.push_str(&val_to_string(v)); }
+                next += 1;
+            }
+            _ => out.push(c),
+        }
+    }
+    if next != values.len() { return Err(RuntimeError::FormatArgs { expected: next, got: values.len() }); }
+    Ok(Value::Str(out))
+}
+
+fn val_to_string(v: &Value) -> String { show(v, &mut Vec::new()) }
+
+/// `open`: a kiírás alatt álló tömbök, map-ek és objektumok; az önmagát tartalmazó érték `...`-ként jelenik meg.
+fn show(v: &Value, open: &mut Vec<*const ()>) -> String {
+    let ptr = match v {
+        Value::Array(a) => Rc::as_ptr(a) as *const (),
+        Value::Map(m) => Rc::as_ptr(m) as *const (),
+        Value::Object(o) => Rc::as_ptr(o) as *const (),
+        _ => std::ptr::null(),
+    };
+    if !ptr.is_null() {
+        if open.contains(&ptr) { return "...".to_string(); }
+        open.push(ptr);
+    }
+    let s = match v {
         Value::Int(n) => n.to_string(),
+        // véges értékre a `{:?}` alak literálként visszaolvasható (`1.0`, `0.1`, `1e-7`); `inf`, `-inf`
+        // és `NaN` nem literál, csak a `float` olvassa vissza
+        Value::Float(x) => format!("{:?}", x),
+        #[cfg(feature = "bigint")]
+        Value::BigInt(n) => n.to_string(),
         Value::Str(s) => s.clone(),
         Value::Bool(b) => b.to_string(),
         Value::Array(a) => {
-            let inner = a.iter().map(val_to_string).collect::<Vec<_>>().join(", ");
+            let inner = a.borrow().iter().map(|x| show_nested(x, open)).collect::<Vec<_>>().join(", ");
             format!("[{}]", inner)
         }
+        Value::Map(m) => {
+            let inner = m.borrow().iter().map(|(k, v)| format!("{}: {}", k.describe(), show_nested(v, open))).collect::<Vec<_>>().join(", ");
+            format!("{{{}}}", inner)
+        }
+        Value::Object(o) => {
+            let o = o.borrow();
+            let inner = o.class.fields.iter().zip(&o.fields).map(|(n, v)| format!("{}: {}", n, show_nested(v, open))).collect::<Vec<_>>().join(", ");
+            if inner.is_empty() { format!("{} {{}}", o.class.name) } else { format!("{} {{ {} }}", o.class.name, inner) }
+        }
+        Value::Func(c) => format!("<fn {}>", c.name),
+        Value::Builtin(b) => format!("<fn {}>", b.name()),
         Value::Void => "()".to_string(),
+    };
+    if !ptr.is_null() { open.pop(); }
+    s
+}
+
+/// Tömb-, map- és mezőelem kiírása: a string idézőjelben, hogy `{2: "b"}` és `{"2": "b"}` elváljon.
+fn show_nested(v: &Value, open: &mut Vec<*const ()>) -> String {
+    match v {
+        Value::Str(s) => format!("{:?}", s),
+        other => show(other, open),
     }
 }
 
-fn apply_binop(a: &Value, b: &Value, op: &Op) -> Result<Value> {
+fn apply_binop(a: &Value, b: &Value, op: &Op) -> std::result::Result<Value, RuntimeError> {
     use Value::*;
     Ok(match (op, a, b) {
-        (Op::Add, Int(x), Int(y)) => Int(x + y),
-        (Op::Sub, Int(x), Int(y)) => Int(x - y),
-        (Op::Mul, Int(x), Int(y)) => Int(x * y),
-        (Op::Div, Int(x), Int(y)) => Int(x / y),
+        (Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Mod, Int(x), Int(y)) => int_arith(op, *x, *y)?,
         (Op::Eq,  Int(x), Int(y)) => Bool(x == y),
         (Op::Ne,  Int(x), Int(y)) => Bool(x != y),
         (Op::Lt,  Int(x), Int(y)) => Bool(x <  y),
         (Op::Le,  Int(x), Int(y)) => Bool(x <= y),
         (Op::Gt,  Int(x), Int(y)) => Bool(x >  y),
         (Op::Ge,  Int(x), Int(y)) => Bool(x >= y),
-        _ => return Err(anyhow!("Nem támogatott művelet vagy típuspár")),
+        (Op::Add, Str(x), Str(y)) => Str(format!("{}{}", x, y)),
+        // a stringek sorrendje a Unicode kódpontoké
+        (Op::Eq,  Str(x), Str(y)) => Bool(x == y),
+        (Op::Ne,  Str(x), Str(y)) => Bool(x != y),
+        (Op::Lt,  Str(x), Str(y)) => Bool(x <  y),
+        (Op::Le,  Str(x), Str(y)) => Bool(x <= y),
+        (Op::Gt,  Str(x), Str(y)) => Bool(x >  y),
+        (Op::Ge,  Str(x), Str(y)) => Bool(x >= y),
+        (Op::Eq,  Bool(x), Bool(y)) => Bool(x == y),
+        (Op::Ne,  Bool(x), Bool(y)) => Bool(x != y),
+        // Void bármivel összevethető (`n.next == Void`); a tömb, map és objektum azonossága a referenciáé
+        (Op::Eq | Op::Ne, Void, _) | (Op::Eq | Op::Ne, _, Void) => Bool(matches!((a, b), (Void, Void)) == matches!(op, Op::Eq)),
+        (Op::Eq | Op::Ne, Array(x), Array(y)) => Bool(Rc::ptr_eq(x, y) == matches!(op, Op::Eq)),
+        (Op::Eq | Op::Ne, Map(x), Map(y)) => Bool(Rc::ptr_eq(x, y) == matches!(op, Op::Eq)),
+        (Op::Eq | Op::Ne, Object(x), Object(y)) => Bool(Rc::ptr_eq(x, y) == matches!(op, Op::Eq)),
+        #[cfg(feature = "bigint")]
+        (_, Int(_) | BigInt(_), Int(_) | BigInt(_)) => big::binop(op, a, b)?,
+        // ha bármelyik oldal Float, mindkettő Float-ként számol (IEEE 754: `1.0 / 0` végtelen)
+        _ if is_number(a) && is_number(b) => {
+            let (x, y) = (as_f64(a), as_f64(b));
+            match op {
+                Op::Add => Float(x + y),
+                Op::Sub => Float(x - y),
+                Op::Mul => Float(x * y),
+                Op::Div => Float(x / y),
+                Op::Mod => Float(x % y),
+                Op::Eq  => Bool(x == y),
+                Op::Ne  => Bool(x != y),
+                Op::Lt  => Bool(x <  y),
+                Op::Le  => Bool(x <= y),
+                Op::Gt  => Bool(x >  y),
+                Op::Ge  => Bool(x >= y),
+                _ => return Err(RuntimeError::TypeMismatch { op: op_symbol(op).to_string(), left: a.type_name().to_string(), right: b.type_name().to_string() }),
+            }
+        }
+        _ => return Err(RuntimeError::TypeMismatch { op: op_symbol(op).to_string(), left: a.type_name().to_string(), right: b.type_name().to_string() }),
     })
 }
+
+fn apply_unop(v: &Value, op: &Op) -> std::result::Result<Value, RuntimeError> {
+    Ok(match (op, v) {
+        (Op::Neg, Value::Int(x)) => match x.checked_neg() {
+            Some(n) => Value::Int(n),
+            None => int_overflow(op, 0, *x)?,
+        },
+        #[cfg(feature = "bigint")]
+        (Op::Neg, Value::BigInt(x)) => big::norm(-x),
+        (Op::Neg, Value::Float(x)) => Value::Float(-x),
+        (Op::Not, Value::Bool(b)) => Value::Bool(!b),
+        _ => return Err(RuntimeError::UnaryTypeMismatch { op: op_symbol(op).to_string(), operand: v.type_name().to_string() }),
+    })
+}
+
+/// Ellenőrzött egész aritmetika: a nullával osztás és a túlcsordulás futásidejű hiba.
+fn int_arith(op: &Op, x: i64, y: i64) -> std::result::Result<Value, RuntimeError> {
+    if matches!(op, Op::Div | Op::Mod) && y == 0 { return Err(RuntimeError::DivisionByZero); }
+    let r = match op {
+        Op::Add => x.checked_add(y),
+        Op::Sub => x.checked_sub(y),
+        Op::Mul => x.checked_mul(y),
+        Op::Div => x.checked_div(y),
+        // `i64::MIN % -1` matematikailag 0, csak a gépi művelet csordul túl
+        Op::Mod => if y == -1 { Some(0) } else { x.checked_rem(y) },
+        _ => unreachable!("arithmetic op"),
+    };
+    match r { Some(n) => Ok(Value::Int(n)), None => int_overflow(op, x, y) }
+}
+
+/// Túlcsordulás: a `bigint` feature-rel az eredmény tetszőleges pontosságú egész, különben hiba.
+fn int_overflow(op: &Op, x: i64, y: i64) -> std::result::Result<Value, RuntimeError> {
+    #[cfg(feature = "bigint")]
+    { big::binop(if matches!(op, Op::Neg) { &Op::Sub } else { op }, &Value::Int(x), &Value::Int(y)) }
+    #[cfg(not(feature = "bigint"))]
+    { Err(RuntimeError::IntegerOverflow { op: op_symbol(if matches!(op, Op::Neg) { &Op::Sub } else { op }).to_string(), left: x.to_string(), right: y.to_string() }) }
+}
+
+fn is_number(v: &Value) -> bool {
+    #[cfg(feature = "bigint")]
+    if matches!(v, Value::BigInt(_)) { return true; }
+    matches!(v, Value::Int(_) | Value::Float(_))
+}
+
+fn as_f64(v: &Value) -> f64 {
+    match v {
+        Value::Int(n) => *n as f64,
+        Value::Float(x) => *x,
+        #[cfg(feature = "bigint")]
+        Value::BigInt(n) => num_traits::ToPrimitive::to_f64(n).unwrap_or(f64::NAN),
+        _ => unreachable!("numeric operand"),
+    }
+}
+
+/// Tetszőleges pontosságú egészek (`--features bigint`). Az i64-be férő eredmény mindig Int, így
+/// a BigInt csak túlcsordulás után jelenik meg.
+#[cfg(feature = "bigint")]
+mod big {
+    use num_bigint::BigInt;
+    use num_traits::{ToPrimitive, Zero};
+
+    use super::{op_symbol, Op, RuntimeError, Value};
+
+    pub fn norm(n: BigInt) -> Value { match n.to_i64() { Some(i) => Value::Int(i), None => Value::BigInt(n) } }
+
+    fn to_big(v: &Value) -> BigInt {
+        match v { Value::Int(n) => BigInt::from(*n), Value::BigInt(n) => n.clone(), _ => unreachable!("integer operand") }
+    }
+
+    pub fn binop(op: &Op, a: &Value, b: &Value) -> Result<Value, RuntimeError> {
+        let (x, y) = (to_big(a), to_big(b));
+        Ok(match op {
+            Op::Add => norm(x + y),
+            Op::Sub => norm(x - y),
+            Op::Mul => norm(x * y),
+            Op::Div | Op::Mod if y.is_zero() => return Err(RuntimeError::DivisionByZero),
+            Op::Div => norm(x / y),
+            Op::Mod => norm(x % y),
+            Op::Eq  => Value::Bool(x == y),
+            Op::Ne  => Value::Bool(x != y),
+            Op::Lt  => Value::Bool(x <  y),
+            Op::Le  => Value::Bool(x <= y),
+            Op::Gt  => Value::Bool(x >  y),
+            Op::Ge  => Value::Bool(x >= y),
+            _ => return Err(RuntimeError::TypeMismatch { op: op_symbol(op).to_string(), left: a.type_name().to_string(), right: b.type_name().to_string() }),
+        })
+    }
+}
+
+fn op_symbol(op: &Op) -> &'static str {
+    match op {
+        Op::Add => "+", Op::Sub | Op::Neg => "-", Op::Mul => "*", Op::Div => "/", Op::Mod => "%", Op::Not => "!",
+        Op::Eq => "==", Op::Ne => "!=", Op::Lt => "<", Op::Le => "<=", Op::Gt => ">", Op::Ge => ">=",
+        _ => "?",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{langpack::SearchPath, loader::Loader};
+
+    fn run(src: &str) -> Result<Value> {
+        let search = SearchPath::new(Vec::new());
+        VM::new(Loader::new(&search).compile("test.rn", src.to_string(), "en")?).run("main")
+    }
+
+    #[test]
+    fn void_and_identity_compare() {
+        let v = run("class N { var next; }\nfn main() { let n = N(); return [n.next == Void, n != Void, n == n, n == N(), [1] == [1]]; }").unwrap();
+        assert_eq!(val_to_string(&v), "[true, true, true, false, false]");
+    }
+
+    #[test]
+    fn nested_strings_are_quoted() {
+        let v = run(r#"fn main() { return [{2: "b", "2": "b"}, "a\"b", 1]; }"#).unwrap();
+        assert_eq!(val_to_string(&v), r#"[{2: "b", "2": "b"}, "a\"b", 1]"#);
+        assert_eq!(val_to_string(&Value::Str("raw".into())), "raw");
+    }
+
+    /// A szkripthívások nem a Rust vermén ágyazódnak: a mély rekurzió a tesztszál kis vermén is fut,
+    /// a végtelen pedig elkapható hiba, nem a folyamat összeomlása.
+    #[test]
+    fn deep_recursion() {
+        let v = run("fn f(n) { if (n == 0) { return 0; } return f(n - 1) + 1; }\nfn main() { return f(20000); }").unwrap();
+        assert_eq!(val_to_string(&v), "20000");
+        let v = run("fn f(n) { return f(n + 1); }\nfn main() { try { f(0); } catch (e) { return e[\"code\"]; } }").unwrap();
+        assert_eq!(val_to_string(&v), "E0421");
+        // a map visszahívásai is a keretveremben futnak
+        let v = run("fn f(n) { if (n == 0) { return [0]; } return map(f(n - 1), fn(x) { return x + 1; }); }\nfn g(n) { if (n == 0) { return 0; } return map([n], fn(x) { return g(x - 1); })[0] + 1; }\nfn main() { return [f(3000), g(3000)]; }").unwrap();
+        assert_eq!(val_to_string(&v), "[[3000], 3000]");
+        let e = run("fn f(n) { return map([n], fn(x) { return f(x + 1); }); }\nfn main() { return f(0); }").unwrap_err();
+        assert!(matches!(e, RunaError::Runtime { kind: RuntimeError::CallDepth(MAX_CALL_DEPTH), .. }), "{:?}", e);
+    }
+
+    #[test]
+    fn closures() {
+        let v = run("fn counter() { var n = 0; return fn() { n += 1; return n; }; }\nfn main() { let c = counter(); let d = counter(); c(); c(); return [c(), d()]; }").unwrap();
+        assert_eq!(val_to_string(&v), "[3, 1]");
+        // két closure ugyanazt a befogott változót látja
+        let v = run("fn pair() { var x = 0; return [fn() { x += 1; }, fn() { return x; }]; }\nfn main() { let p = pair(); let inc = p[0]; let get = p[1]; inc(); inc(); return get(); }").unwrap();
+        assert_eq!(val_to_string(&v), "2");
+        let v = run("fn main() { let k = 10; return [map([1, 2], fn(x) { return x + k; }), filter([1, 2, 3, 4], fn(x) { return x % 2 == 0; })]; }").unwrap();
+        assert_eq!(val_to_string(&v), "[[11, 12], [2, 4]]");
+        // befogás után a függvény írása a közös cellába megy (`Slot::Shared`), egymásba ágyazva is
+        let v = run("fn main() { var y = 1; let f = fn() { return y; }; y = 5; var z = 1; let h = fn() { return fn() { z += 1; return z; }; }(); h(); return [f(), h(), z]; }").unwrap();
+        assert_eq!(val_to_string(&v), "[5, 3, 3]");
+        // a ciklusváltozó minden körben új cella
+        let v = run("fn main() { var fs = []; for (i in [1, 2, 3]) { push(fs, fn() { return i; }); } var i = 0; while (i < 3) { let j = i; push(fs, fn() { return j; }); i += 1; } return map(fs, fn(g) { return g(); }); }").unwrap();
+        assert_eq!(val_to_string(&v), "[1, 2, 3, 0, 1, 2]");
+    }
+
+    #[test]
+    fn builtins_as_values() {
+        let v = run("class Box { let f; fn Box(f) { self.f = f; } }\nfn main() { let f = len; let m = {\"t\": trim}; let b = Box(upper); return [map([\"a\", \"bb\"], len), f(\"abc\"), m[\"t\"](\" x \"), b.f(\"y\"), map([[1, 2], [3]], fn(x) { return map(x, float); }), f]; }").unwrap();
+        assert_eq!(val_to_string(&v), "[[1, 2], 3, \"x\", \"Y\", [[1.0, 2.0], [3.0]], <fn len>]");
+        // a saját függvény és a helyi változó elfedi a beépítettet
+        let v = run("fn upper(s) { return s; }\nfn main() { let len = 2; return [map([\"a\"], upper), len]; }").unwrap();
+        assert_eq!(val_to_string(&v), "[[\"a\"], 2]");
+        // visszahívásként a `map` egy argumentumot kap, a `filter`-nek Bool kell
+        assert_eq!(run("fn main() { return map([1], map); }").unwrap_err().code(), "E0403");
+        assert_eq!(run("fn main() { return filter([\"a\"], len); }").unwrap_err().code(), "E0406");
+    }
+
+    #[test]
+    fn int_overflow() {
+        let v = run("fn main() { let m = 9223372036854775807; return [m + 1, -m - 2, m * 2]; }");
+        #[cfg(not(feature = "bigint"))]
+        assert_eq!(v.unwrap_err().code(), "E0417");
+        // bigint-tel túlcsordulás helyett nagy egész
+        #[cfg(feature = "bigint")]
+        assert_eq!(val_to_string(&v.unwrap()), "[9223372036854775808, -9223372036854775809, 18446744073709551614]");
+    }
+
+    #[test]
+    fn try_catch_unwinding() {
+        // a kezelő a `try` eleji veremmélységre áll vissza, a hívott függvény kereteit is eldobja
+        let v = run("fn boom(n) { return 1 + (2 + (n / 0)); }\nfn main() { var out = []; for (i in [1, 2, 3]) { try { push(out, 100 + [i, boom(i)][0]); } catch (e) { push(out, -i); } } return [1 + 2 * 3, out]; }").unwrap();
+        assert_eq!(val_to_string(&v), "[7, [-1, -2, -3]]");
+        // break, continue és return a `try` blokkból
+        let v = run("fn f() { try { return 1; } catch (e) { return 2; } }\nfn main() { var out = []; for (i in [1, 2, 3, 4]) { try { if (i == 2) { continue; } if (i == 4) { break; } push(out, i); } catch (e) { push(out, 0); } } var k = 0; while (k < 3) { k += 1; try { if (k == 1) { continue; } push(out, k / 0); } catch (e) { push(out, k * 10); } } push(out, f()); return out; }").unwrap();
+        assert_eq!(val_to_string(&v), "[1, 3, 20, 30, 1]");
+        // a belső `catch`-ben keletkező hibát a külső `try` kapja el
+        let v = run("fn main() { try { try { 1 / 0; } catch (e) { return e[\"code\"] + 1; } } catch (e) { return \"outer \" + e[\"code\"]; } }").unwrap();
+        assert_eq!(val_to_string(&v), "outer E0405");
+    }
+
+    #[test]
+    fn shadowing_and_slot_reuse() {
+        // a második blokk `y`-ja az első blokk befogott `x`-ének helyére kerül, de új változó:
+        // az írása nem látszik a closure-ben, a belső `x` pedig nem írja a külsőt
+        let v = run("fn main() { var x = 1; let h = fn() { return x; }; var f = 0; if (true) { var x = 10; f = fn() { return x; }; x = 11; } if (true) { var y = 20; let g = fn() { return y; }; y = 22; x = g(); } return [x, h(), f()]; }").unwrap();
+        assert_eq!(val_to_string(&v), "[22, 22, 11]");
+    }
+
+    #[test]
+    fn strings() {
+        // karakterenként indexel és szeletel, nem bájtonként
+        let v = run("fn main() { let s = \"árvíztűrő tükörfúrógép\"; return [len(s), s[0], s[8], s[0:5], s[10:], s[:3], find(s, \"tük\")]; }").unwrap();
+        assert_eq!(val_to_string(&v), "[22, \"á\", \"ő\", \"árvíz\", \"tükörfúrógép\", \"árv\", 10]");
+        // a szelet határai a hosszra szorulnak, az index viszont hiba
+        let v = run("fn main() { let s = \"űrő\"; return [s[1:100], s[-5:2], s[2:1], s[3:]]; }").unwrap();
+        assert_eq!(val_to_string(&v), "[\"rő\", \"űr\", \"\", \"\"]");
+        for src in ["fn main() { return \"űrő\"[3]; }", "fn main() { return \"űrő\"[-4]; }"] {
+            assert_eq!(run(src).unwrap_err().code(), "E0404", "{}", src);
+        }
+        assert_eq!(run("fn main() { return \"űrő\"[1:\"x\"]; }").unwrap_err().code(), "E0405");
+        // kódpont szerinti összehasonlítás, összefűzés csak szövegek között
+        let v = run("fn main() { return [\"alma\" < \"körte\", \"Á\" > \"Z\", \"a\" < \"ab\", \"é\" == \"é\", \"ab\" + \"ő\" + \"c\"]; }").unwrap();
+        assert_eq!(val_to_string(&v), "[true, true, true, true, \"abőc\"]");
+        for src in ["fn main() { return \"a\" + 1; }", "fn main() { return \"a\" < 1; }"] {
+            assert_eq!(run(src).unwrap_err().code(), "E0405", "{}", src);
+        }
+    }
+
+    #[test]
+    fn inheritance() {
+        let src = "class Animal { var name; var sound = \"...\"; fn Animal(n) { self.name = n; } fn speak() { return self.name + \" says \" + self.sound; } fn kind() { return \"animal\"; } fn describe() { return self.kind() + \":\" + self.name; } }
+class Cat : Animal { }
+class Dog : Animal { var tail = 1; fn Dog(n) { super.Animal(n); self.sound = \"woof\"; } fn kind() { return \"dog\"; } fn speak() { return super.speak() + \"!\"; } }
+class Puppy : Dog { fn Puppy(n) { super.Dog(n + \" jr\"); } fn speak() { return super.speak() + \"?\"; } }
+";
+        // az ős metódusából hívott `self.kind()` a felülírt változatot éri el; a `super` lánc az ősök felé halad
+        let v = run(&format!("{}fn main() {{ let d = Dog(\"Rex\"); let p = Puppy(\"Rex\"); return [d.speak(), d.describe(), p.speak(), p.describe(), p.tail, Cat(\"Tom\").describe()]; }}", src)).unwrap();
+        assert_eq!(val_to_string(&v), "[\"Rex says woof!\", \"dog:Rex\", \"Rex jr says woof!?\", \"dog:Rex jr\", 1, \"animal:Tom\"]");
+        // saját konstruktor nélkül az ősé örököl, az aritásával együtt
+        let e = run(&format!("{}fn main() {{ return Cat(); }}", src)).unwrap_err();
+        assert!(matches!(&e, RunaError::Runtime { kind: RuntimeError::ArityMismatch { name, expected: 1, got: 0 }, .. } if name == "Cat"), "{:?}", e);
+        assert_eq!(run(&format!("{}fn main() {{ return Dog(\"Rex\").fly(); }}", src)).unwrap_err().code(), "E0408");
+    }
+
+    #[test]
+    fn globals_init_order() {
+        // a kezdőértékek fentről lefelé futnak a belépési pont előtt, a függvények a fájlban bárhol lehetnek
+        let v = run("let order = [];\nlet a = log(\"a\", 1);\nvar b = log(\"b\", a + 1);\nlet c = log(\"c\", b * 10);\nfn log(name, v) { push(order, name); return v; }\nfn main() { b += 1; push(order, \"main\"); return [order, a, b, c]; }").unwrap();
+        assert_eq!(val_to_string(&v), "[[\"a\", \"b\", \"c\", \"main\"], 1, 3, 20]");
+        // lentebb deklarált globális: közvetlenül fordítási, függvényen át futási hiba
+        assert_eq!(run("let x = y + 1;\nlet y = 1;\nfn main() { return x; }").unwrap_err().code(), "E0319");
+        assert_eq!(run("let x = f();\nfn f() { return y; }\nlet y = 1;\nfn main() { return x; }").unwrap_err().code(), "E0411");
+    }
+
+    #[test]
+    fn for_in() {
+        let v = run("fn main() { var out = []; for (x in [1, 2]) { push(out, x); } for (c in \"ző\") { push(out, c); } for (k in {\"a\": 1, 2: 3}) { push(out, k); } return out; }").unwrap();
+        assert_eq!(val_to_string(&v), "[1, 2, \"z\", \"ő\", \"a\", 2]");
+        // a hiba a bejárt kifejezésre mutat, nem az egész ciklusra
+        let e = run("fn main() {\n  for (x in 5) { }\n}").unwrap_err();
+        assert!(matches!(&e, RunaError::Runtime { kind: RuntimeError::NotIterable(got), span: Some(s) } if got == "Int" && s.line == 2 && s.col == 13), "{:?}", e);
+    }
+
+    #[test]
+    fn let_fields() {
+        let v = run("class P { let id; let kind = \"p\"; fn P(v) { self.id = v; } }\nclass Q : P { fn Q(v) { super.P(v * 2); } }\nfn main() { return [P(1).id, Q(2).id, Q(2).kind]; }").unwrap();
+        assert_eq!(val_to_string(&v), "[1, 4, \"p\"]");
+        let e = run("class P { let id; fn P(v) { self.id = v; } }\nfn main() { let p = P(1); p.id = 2; }").unwrap_err();
+        assert_eq!(e.code(), "E0419");
+        // a konstruktor nem hívható újra metódusként
+        let e = run("class P { let id; fn P(v) { self.id = v; } }\nfn main() { let p = P(1); p.P(2); }").unwrap_err();
+        assert_eq!(e.code(), "E0408");
+        let e = run("class P { let id; fn P(v) { self.id = v; } }\nclass Q : P { fn reset() { super.P(0); } }\nfn main() { Q(1).reset(); }").unwrap_err();
+        assert_eq!(e.code(), "E0408");
+    }
+
+    #[test]
+    fn logic_needs_bools() {
+        assert_eq!(val_to_string(&run("fn main() { return [true && false, false || true, 1 < 2 || 1 / 0 == 0]; }").unwrap()), "[false, true, true]");
+        for src in ["fn main() { return false || 7; }", "fn main() { return 1 || true; }", "fn main() { return true && Void; }"] {
+            let e = run(src).unwrap_err();
+            assert!(matches!(e, RunaError::Runtime { kind: RuntimeError::NotABool { .. }, .. }), "{}: {:?}", src, e);
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! A modulbetöltő integrációs tesztjei a `tests/fixtures/loader` fájljain.
+
+use std::{path::PathBuf, process::{Command, Output}};
+
+fn run(file: &str) -> Output {
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", "loader", file].iter().collect();
+    Command::new(env!("CARGO_BIN_EXE_runa")).arg("--file").arg(&path).output().unwrap()
+}
+
+fn stdout(file: &str) -> String {
+    let out = run(file);
+    assert!(out.status.success(), "{}: {}", file, String::from_utf8_lossy(&out.stderr));
+    String::from_utf8(out.stdout).unwrap()
+}
+
+fn error(file: &str) -> String {
+    let out = run(file);
+    assert!(!out.status.success(), "{} should not compile", file);
+    String::from_utf8(out.stderr).unwrap()
+}
+
+#[test]
+fn imports_resolve_relative_to_the_importer() {
+    // a `lib/util.rn`-t a főprogram és a `lib/geometry.rn` is importálja: egyszer töltődik be
+    assert_eq!(stdout("main.rn"), "util loaded\n27 8 25 2\nmain() -> Void\n");
+}
+
+#[test]
+fn modules_keep_their_own_locale() {
+    // magyar program angol (fejléces) modullal, és az angol modul által importált fejléc nélküli modul
+    assert_eq!(stdout("program.hu.rn"), "util loaded\n12\nfo() -> Void\n");
+}
+
+#[test]
+fn import_cycle_is_reported() {
+    let err = error("cycle/a.rn");
+    assert!(err.contains("error[E0316]"), "{}", err);
+    assert!(err.contains("a.rn -> ") && err.contains("b.rn -> ") && err.contains("b.rn:1:1"), "{}", err);
+}
+
+#[test]
+fn missing_module_is_reported() {
+    let err = error("missing.rn");
+    assert!(err.contains("missing.rn:2:1: error[E0315]") && err.contains("nope.rn"), "{}", err);
+}
+
+#[test]
+fn same_module_name_twice_is_reported() {
+    let err = error("dup.rn");
+    assert!(err.contains("dup.rn:2:1: error[E0317]"), "{}", err);
+}